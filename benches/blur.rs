@@ -0,0 +1,37 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use piet::{
+    Color, RenderContext,
+    kurbo::{Rect, RoundedRect, Vec2},
+};
+use piet_raqote::{Cache, RaqoteRenderContext, RenderContextExt};
+use raqote::DrawTarget;
+
+/// Compares the analytic blur of a rounded rect with rasterizing and blurring it as a shadow.
+fn blurred_rounded_rect(c: &mut Criterion) {
+    let mut cache = Cache::new();
+    let mut target = DrawTarget::new(400, 400);
+    let rect = Rect::new(50.3, 60.2, 350.0, 340.7);
+
+    let mut group = c.benchmark_group("blurred_rounded_rect");
+    for blur_radius in [2.0, 6.0, 20.0] {
+        group.bench_function(format!("analytic/{blur_radius}"), |b| {
+            b.iter(|| {
+                let mut ctx = RaqoteRenderContext::new(&mut target, &mut cache);
+                ctx.blurred_rounded_rect(rect, 24.0, blur_radius, &Color::BLACK);
+                ctx.finish().unwrap();
+            })
+        });
+        group.bench_function(format!("fill_shadow/{blur_radius}"), |b| {
+            b.iter(|| {
+                let mut ctx = RaqoteRenderContext::new(&mut target, &mut cache);
+                let shape = RoundedRect::from_rect(rect, 24.0);
+                ctx.fill_shadow(shape, blur_radius, Vec2::ZERO, &Color::BLACK);
+                ctx.finish().unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, blurred_rounded_rect);
+criterion_main!(benches);
@@ -0,0 +1,131 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use piet::{RenderContext, Text, TextLayoutBuilder};
+use piet_cosmic_text::cosmic_text::{Command, SwashCache};
+use piet_raqote::{Cache, RaqoteRenderContext};
+use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source, Winding};
+
+const PARAGRAPH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+    tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud \
+    exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor \
+    in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur \
+    sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est \
+    laborum.";
+
+fn draw_paragraph(c: &mut Criterion) {
+    let mut cache = Cache::new();
+    let mut target = DrawTarget::new(600, 400);
+
+    let layout = {
+        let mut ctx = RaqoteRenderContext::new(&mut target, &mut cache);
+        ctx.text()
+            .new_text_layout(PARAGRAPH.repeat(4))
+            .max_width(580.0)
+            .build()
+            .unwrap()
+    };
+
+    c.bench_function("draw_paragraph", |b| {
+        b.iter(|| {
+            let mut ctx = RaqoteRenderContext::new(&mut target, &mut cache);
+            ctx.draw_text(&layout, (10.0, 10.0));
+            ctx.finish().unwrap();
+        })
+    });
+}
+
+/// Compares filling the glyphs of the paragraph one at a time with filling each layout run as a
+/// single merged path, which is what `draw_text` would do if it batched glyphs.
+fn fill_glyphs(c: &mut Criterion) {
+    let mut cache = Cache::new();
+    let mut target = DrawTarget::new(600, 400);
+    let mut text = {
+        let mut ctx = RaqoteRenderContext::new(&mut target, &mut cache);
+        ctx.text().clone()
+    };
+    let layout = text
+        .new_text_layout(PARAGRAPH.repeat(4))
+        .max_width(580.0)
+        .build()
+        .unwrap();
+
+    // Collect the outline of every glyph, with the offset that moves it to its place in the
+    // paragraph.
+    let mut swash_cache = SwashCache::new();
+    let runs: Vec<Vec<Glyph>> = text
+        .with_font_system_mut(|system| {
+            layout
+                .layout_runs()
+                .map(|run| {
+                    run.glyphs
+                        .iter()
+                        .filter_map(|glyph| {
+                            let physical = glyph.physical((10.0, 10.0 + run.line_y), 1.0);
+                            let outline =
+                                swash_cache.get_outline_commands(system, physical.cache_key)?;
+                            Some((outline.to_vec(), (physical.x as f32, physical.y as f32)))
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+        .unwrap();
+
+    let per_glyph: Vec<raqote::Path> = runs.iter().flatten().map(|glyph| path(&[glyph])).collect();
+    let per_run: Vec<raqote::Path> = runs
+        .iter()
+        .map(|run| path(&run.iter().collect::<Vec<_>>()))
+        .collect();
+
+    let source = Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0));
+    let options = DrawOptions::new();
+    let mut group = c.benchmark_group("fill_glyphs");
+    for (name, paths) in [("per_glyph", &per_glyph), ("per_run", &per_run)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for path in paths {
+                    target.fill(path, &source, &options);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The outline of a glyph, and its offset.
+type Glyph = (Vec<Command>, (f32, f32));
+
+/// Builds a single even-odd path from the outlines of `glyphs`, flipped into y-down coordinates.
+fn path(glyphs: &[&Glyph]) -> raqote::Path {
+    let mut builder = PathBuilder::new();
+    for (outline, (x, y)) in glyphs {
+        let place = |p: [f32; 2]| (x + p[0], y - p[1]);
+        for cmd in outline {
+            match *cmd {
+                Command::MoveTo(p) => {
+                    let (px, py) = place(p.into());
+                    builder.move_to(px, py);
+                }
+                Command::LineTo(p) => {
+                    let (px, py) = place(p.into());
+                    builder.line_to(px, py);
+                }
+                Command::CurveTo(a, b, p) => {
+                    let ((ax, ay), (bx, by), (px, py)) =
+                        (place(a.into()), place(b.into()), place(p.into()));
+                    builder.cubic_to(ax, ay, bx, by, px, py);
+                }
+                Command::QuadTo(a, p) => {
+                    let ((ax, ay), (px, py)) = (place(a.into()), place(p.into()));
+                    builder.quad_to(ax, ay, px, py);
+                }
+                Command::Close => builder.close(),
+            }
+        }
+    }
+    let mut path = builder.finish();
+    path.winding = Winding::EvenOdd;
+    path
+}
+
+criterion_group!(benches, draw_paragraph, fill_glyphs);
+criterion_main!(benches);
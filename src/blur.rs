@@ -0,0 +1,270 @@
+//! Gaussian blurs of masks and images.
+//!
+//! Blur radii follow the convention of [`piet::util::compute_blurred_rect`]: a radius of `r`
+//! blurs with a standard deviation of `r / √2`, and the blur extends `2.5 * r` beyond the shape.
+
+use piet::kurbo::{Affine, Rect, RoundedRectRadii};
+use raqote::{DrawOptions, DrawTarget, Mask, SolidSource, Source};
+
+use crate::convert;
+
+/// The distance that a blur extends beyond the blurred shape, relative to the blur radius.
+pub(crate) const BLUR_EXTENT: f64 = 2.5;
+
+/// The largest blur radius that masks are computed with, in pixels of the mask.
+///
+/// Wider blurs are computed at a lower resolution and scaled up, which is indistinguishable
+/// because they don't have any sharp detail.
+pub(crate) const MAX_MASK_BLUR_RADIUS: f64 = 32.0;
+
+/// Rasterizes `path` and blurs it into a mask.
+///
+/// `bounds` is the bounding box of the path, and `transform` maps the path into the space of the
+/// mask. Returns the mask along with the pixel-aligned rectangle that it covers, or `None` if the
+/// mask is empty. The mask is only accurate within `visible`, and the pixels next to it.
+pub(crate) fn shape_mask(
+    path: &raqote::Path,
+    bounds: Rect,
+    transform: Affine,
+    blur_radius: f64,
+    visible: Rect,
+) -> Option<(Mask, Rect)> {
+    let padding = BLUR_EXTENT * blur_radius;
+    let mask_rect = mask_rect(transform.transform_rect_bbox(bounds), padding, visible)?;
+    let width = mask_rect.width() as i32;
+    let height = mask_rect.height() as i32;
+
+    let mut coverage = DrawTarget::new(width, height);
+    coverage.set_transform(&convert::to_transform(
+        Affine::translate(-mask_rect.origin().to_vec2()) * transform,
+    ));
+    coverage.fill(
+        path,
+        &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0, 0, 0)),
+        &DrawOptions::new(),
+    );
+
+    let mut mask = Mask {
+        width,
+        height,
+        data: coverage
+            .get_data()
+            .iter()
+            .map(|pixel| (pixel >> 24) as u8)
+            .collect(),
+    };
+    blur_mask(&mut mask, blur_radius);
+
+    Some((mask, mask_rect))
+}
+
+/// Returns the pixel-aligned rectangle that the mask of a blur of `shape_rect` covers, or `None`
+/// if it is empty.
+///
+/// The mask extends `padding` beyond the shape, but is cropped to the area that affects the
+/// pixels in and next to `visible`.
+fn mask_rect(shape_rect: Rect, padding: f64, visible: Rect) -> Option<Rect> {
+    let rect = shape_rect
+        .inflate(padding, padding)
+        .intersect(visible.inflate(padding + 1.0, padding + 1.0))
+        .expand();
+
+    // Rects that don't intersect have a negative size, and non-finite rects come from transforms
+    // that can't be inverted, which make nothing visible.
+    (rect.is_finite() && rect.width() >= 1.0 && rect.height() >= 1.0).then_some(rect)
+}
+
+/// The maximum height of the slabs that the corners of a rounded rect are split into.
+const SLAB_HEIGHT: f64 = 0.5;
+
+/// Computes the blur mask for `rect` with rounded corners, along with the pixel-aligned rectangle
+/// that it covers.
+///
+/// Rather than blurring a rasterized shape, the rounded rect is split into horizontal slabs, within
+/// which its left and right edges are almost constant. The blur of each slab is separable, and is
+/// computed exactly. Only the corners need thin slabs, so most pixels only sum a few of them.
+///
+/// Like [`shape_mask`], the mask is only accurate within `visible`, and the pixels next to it.
+pub(crate) fn rounded_rect_mask(
+    rect: Rect,
+    radii: RoundedRectRadii,
+    blur_radius: f64,
+    visible: Rect,
+) -> Option<(Mask, Rect)> {
+    let padding = BLUR_EXTENT * blur_radius;
+    let mask_rect = mask_rect(rect, padding, visible)?;
+    let width = mask_rect.width() as usize;
+    let height = mask_rect.height() as usize;
+
+    // Split the rect into thin slabs along the corners, and a single slab between them. Slabs
+    // further than `padding` from the mask barely affect it, so the corners are only split up
+    // near the mask.
+    let top = radii.top_left.max(radii.top_right);
+    let bottom = radii.bottom_left.max(radii.bottom_right);
+    let (near_start, near_end) = (mask_rect.y0 - padding, mask_rect.y1 + padding);
+    let mut boundaries = vec![rect.y0];
+    for (start, end) in [(rect.y0, rect.y0 + top), (rect.y1 - bottom, rect.y1)] {
+        let (start, end) = (start.max(near_start), end.min(near_end));
+        if start >= end {
+            continue;
+        }
+        let steps = ((end - start) / SLAB_HEIGHT).ceil().max(1.0);
+        for step in 0..=steps as usize {
+            let y = start + (end - start) * step as f64 / steps;
+            if y > *boundaries.last().unwrap() {
+                boundaries.push(y);
+            }
+        }
+    }
+    if rect.y1 > *boundaries.last().unwrap() {
+        boundaries.push(rect.y1);
+    }
+
+    // The horizontal blur of each slab, sampled at the pixel centers of the mask.
+    let recip = blur_radius.recip();
+    let profiles: Vec<Vec<f32>> = boundaries
+        .windows(2)
+        .map(|slab| {
+            let (left, right) = horizontal_extent(rect, radii, (slab[0] + slab[1]) / 2.0);
+            (0..width)
+                .map(|i| {
+                    let x = mask_rect.x0 + i as f64 + 0.5;
+                    (0.5 * (erf((right - x) * recip) - erf((left - x) * recip))) as f32
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut data = vec![0; width * height];
+    let mut sum = vec![0.0; width];
+    for (j, row) in data.chunks_exact_mut(width).enumerate() {
+        let y = mask_rect.y0 + j as f64 + 0.5;
+
+        sum.fill(0.0);
+        for (slab, profile) in boundaries.windows(2).zip(&profiles) {
+            // The vertical blur of the slab.
+            let weight = 0.5 * (erf((slab[1] - y) * recip) - erf((slab[0] - y) * recip));
+            if weight < 1e-5 {
+                continue;
+            }
+
+            for (sum, value) in sum.iter_mut().zip(profile) {
+                *sum += weight as f32 * value;
+            }
+        }
+
+        for (dst, value) in row.iter_mut().zip(&sum) {
+            *dst = (255.0 * value).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    let mask = Mask {
+        width: width as i32,
+        height: height as i32,
+        data,
+    };
+
+    Some((mask, mask_rect))
+}
+
+/// Returns the left and right edges of `rect` with rounded corners at the height `y`.
+fn horizontal_extent(rect: Rect, radii: RoundedRectRadii, y: f64) -> (f64, f64) {
+    // The horizontal distance from the edge of the rect to a corner of radius `r`, at a vertical
+    // distance of `d` from the edge.
+    let inset = |r: f64, d: f64| {
+        if d < r {
+            r - (r * r - (r - d) * (r - d)).sqrt()
+        } else {
+            0.0
+        }
+    };
+
+    let (top, bottom) = (y - rect.y0, rect.y1 - y);
+    let left = inset(radii.top_left, top).max(inset(radii.bottom_left, bottom));
+    let right = inset(radii.top_right, top).max(inset(radii.bottom_right, bottom));
+
+    (rect.x0 + left, rect.x1 - right)
+}
+
+/// Approximates the error function.
+///
+/// This is the same approximation that piet uses for blurred rects. See
+/// <https://raphlinus.github.io/audio/2018/09/05/sigmoid.html> for an explanation.
+fn erf(x: f64) -> f64 {
+    let x = x * std::f64::consts::FRAC_2_SQRT_PI;
+    let xx = x * x;
+    let x = x + (0.24295 + (0.03395 + 0.0104 * xx) * xx) * (x * xx);
+    x / (1.0 + x * x).sqrt()
+}
+
+/// Blurs `mask` in place, treating everything outside of it as uncovered.
+pub(crate) fn blur_mask(mask: &mut Mask, blur_radius: f64) {
+    let width = mask.width as usize;
+    let height = mask.height as usize;
+    let kernel = kernel(blur_radius, width.max(height));
+
+    let mut horizontal = vec![0.0; width * height];
+    for (src, dst) in mask
+        .data
+        .chunks_exact(width)
+        .zip(horizontal.chunks_exact_mut(width))
+    {
+        convolve(src.iter().map(|&v| f32::from(v)), dst.iter_mut(), &kernel);
+    }
+
+    for x in 0..width {
+        let column = horizontal[x..].iter().step_by(width).copied();
+        let output = mask.data[x..].iter_mut().step_by(width);
+        let mut blurred = vec![0.0; height];
+        convolve(column, blurred.iter_mut(), &kernel);
+        for (dst, value) in output.zip(blurred) {
+            *dst = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Returns normalized Gaussian weights for a blur of `blur_radius`, centered on the middle weight.
+///
+/// The kernel extends at most `max_half` weights to either side of the middle, and the weights
+/// beyond that are added to the outermost ones. This doesn't change the blur of data that is
+/// shorter than `max_half`, since all of those weights read the same value beyond its ends.
+pub(crate) fn kernel(blur_radius: f64, max_half: usize) -> Vec<f32> {
+    let full_half = (BLUR_EXTENT * blur_radius).ceil().max(0.0);
+    let half = full_half.min(max_half as f64) as i64;
+    let mut weights: Vec<f64> = (-half..=half)
+        .map(|i| (-(i * i) as f64 / (blur_radius * blur_radius)).exp())
+        .collect();
+    if (half as f64) < full_half {
+        // Approximate the sum of the weights from `half` onwards with the integral of the Gaussian.
+        let recip = blur_radius.recip();
+        let tail = 0.5
+            * std::f64::consts::PI.sqrt()
+            * blur_radius
+            * (erf((full_half + 0.5) * recip) - erf((half as f64 - 0.5) * recip));
+        let last = weights.len() - 1;
+        weights[0] = tail;
+        weights[last] = tail;
+    }
+    let total: f64 = weights.iter().sum();
+
+    weights.iter().map(|w| (w / total) as f32).collect()
+}
+
+/// Convolves `src` with a kernel centered on its middle weight, writing the result to `dst`.
+///
+/// Values beyond the ends of `src` are treated as zero.
+fn convolve<'a>(
+    src: impl ExactSizeIterator<Item = f32>,
+    dst: impl Iterator<Item = &'a mut f32>,
+    kernel: &[f32],
+) {
+    let half = kernel.len() / 2;
+    let mut padded = vec![0.0; src.len() + 2 * half];
+    for (padded, value) in padded[half..].iter_mut().zip(src) {
+        *padded = value;
+    }
+
+    for (window, dst) in padded.windows(kernel.len()).zip(dst) {
+        *dst = window.iter().zip(kernel).map(|(v, k)| v * k).sum();
+    }
+}
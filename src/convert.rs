@@ -1,4 +1,4 @@
-//! Conversions from [`piet`] types to [`raqote`] types.
+//! Conversions between [`piet`] types and [`raqote`] types.
 
 use piet::kurbo::{self, PathEl};
 use piet_cosmic_text::cosmic_text;
@@ -13,6 +13,13 @@ pub fn to_color(color: piet::Color) -> raqote::Color {
     raqote::Color::new(a, r, g, b)
 }
 
+pub fn to_filter_mode(interp: piet::InterpolationMode) -> raqote::FilterMode {
+    match interp {
+        piet::InterpolationMode::NearestNeighbor => raqote::FilterMode::Nearest,
+        piet::InterpolationMode::Bilinear => raqote::FilterMode::Bilinear,
+    }
+}
+
 pub fn to_stops(stops: impl piet::GradientStops) -> Vec<raqote::GradientStop> {
     stops
         .to_vec()
@@ -98,3 +105,13 @@ pub fn text_command(cmd: cosmic_text::Command) -> kurbo::PathEl {
         }
     }
 }
+
+pub fn to_transform(affine: kurbo::Affine) -> raqote::Transform {
+    let [a, b, c, d, e, f] = affine.as_coeffs();
+    raqote::Transform::new(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+}
+
+pub fn from_transform(transform: raqote::Transform) -> kurbo::Affine {
+    let [a, b, c, d, e, f] = transform.to_array();
+    kurbo::Affine::new([a.into(), b.into(), c.into(), d.into(), e.into(), f.into()])
+}
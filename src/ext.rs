@@ -0,0 +1,260 @@
+//! Drawing features that are specific to raqote, and aren't part of piet's [`RenderContext`].
+
+use piet::{
+    IntoBrush, RenderContext,
+    kurbo::{Affine, Rect, RoundedRect, RoundedRectRadii, Shape, Vec2},
+};
+use raqote::{AntialiasMode, BlendMode};
+
+use crate::{Filter, RaqoteRenderContext, blur, convert};
+
+/// Extensions to [`RenderContext`] that expose features of [`raqote`].
+pub trait RenderContextExt: RenderContext {
+    /// Starts drawing into a new layer.
+    ///
+    /// Everything drawn until the matching [`pop_layer`](Self::pop_layer) is composited onto the
+    /// target as a group, with the given `opacity` and `blend_mode`, and limited to `clip` if one
+    /// is given.
+    ///
+    /// If `opacity` or `clip` isn't finite, [`piet::Error::InvalidInput`] is recorded, and the
+    /// layer is still pushed but is fully transparent.
+    ///
+    /// Pushing a layer also saves the context state, as if by [`RenderContext::save`]. Restoring
+    /// that state pops the layer.
+    ///
+    /// While a layer is open, [`RenderContext::clear`],
+    /// [`RenderContext::capture_image_area`] and [`apply_filter`](Self::apply_filter) fail with
+    /// [`piet::Error::NotSupported`], since they access the target beneath the layer.
+    fn push_layer(&mut self, opacity: f32, blend_mode: BlendMode, clip: Option<impl Shape>);
+
+    /// Composites the most recently pushed layer, and restores the state that was saved when it
+    /// was pushed.
+    ///
+    /// Returns [`piet::Error::StackUnbalance`] if the current state wasn't created by
+    /// [`push_layer`](Self::push_layer).
+    fn pop_layer(&mut self) -> Result<(), piet::Error>;
+
+    /// Sets the blend mode used to composite subsequent drawing operations.
+    ///
+    /// The blend mode is part of the context state, and is saved and restored with it. It
+    /// defaults to [`BlendMode::SrcOver`], and doesn't affect [`RenderContext::clear`].
+    fn set_blend_mode(&mut self, blend_mode: BlendMode);
+
+    /// Sets an opacity that subsequent drawing operations are multiplied by.
+    ///
+    /// The alpha is clamped to the range from 0 to 1. Like the blend mode, it is part of the
+    /// context state, and doesn't affect [`RenderContext::clear`].
+    fn set_global_alpha(&mut self, alpha: f32);
+
+    /// Sets how subsequent fills, strokes and clips are antialiased.
+    ///
+    /// With [`AntialiasMode::None`], every pixel is either fully covered or not covered at all. The
+    /// mode is part of the context state, and defaults to [`AntialiasMode::Gray`].
+    fn set_antialias(&mut self, antialias: AntialiasMode);
+
+    /// Fills a blurred copy of `shape`, moved by `offset`, to draw a drop shadow.
+    ///
+    /// The blur radius has the same meaning as in [`RenderContext::blurred_rect`].
+    fn fill_shadow(
+        &mut self,
+        shape: impl Shape,
+        blur_radius: f64,
+        offset: Vec2,
+        brush: &impl IntoBrush<Self>,
+    );
+
+    /// Draws a blurred rect with rounded corners.
+    ///
+    /// This is like [`RenderContext::blurred_rect`], and much faster than using
+    /// [`fill_shadow`](Self::fill_shadow) with a [`RoundedRect`].
+    fn blurred_rounded_rect(
+        &mut self,
+        rect: Rect,
+        radii: impl Into<RoundedRectRadii>,
+        blur_radius: f64,
+        brush: &impl IntoBrush<Self>,
+    );
+
+    /// Applies `filter` in place to the pixels of the target within `rect`.
+    ///
+    /// Like [`RenderContext::clear`], `rect` is in device space, and the transform, clip, blend
+    /// mode and global alpha are ignored. Filters that combine neighboring pixels also read those
+    /// just outside of `rect`, and repeat the pixels at the edges of the target.
+    ///
+    /// Filters can't be applied while a layer is open, and record [`piet::Error::NotSupported`].
+    fn apply_filter(&mut self, rect: Rect, filter: &Filter);
+}
+
+impl<B> RenderContextExt for RaqoteRenderContext<'_, '_, B>
+where
+    B: AsRef<[u32]> + AsMut<[u32]>,
+{
+    fn push_layer(&mut self, opacity: f32, blend_mode: BlendMode, clip: Option<impl Shape>) {
+        // Invalid layers are still pushed, so that they stay paired with their pops, but nothing
+        // drawn into them is composited.
+        let mut opacity = opacity;
+        if !opacity.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            opacity = 0.0;
+        }
+
+        // Saving never fails.
+        let _ = self.save();
+
+        // The clip is pushed before the layer, so that it bounds the layer and stays active while
+        // the layer is composited.
+        if let Some(clip) = clip {
+            if self.check_shape(&clip) {
+                self.clip(clip);
+            } else {
+                opacity = 0.0;
+            }
+        }
+
+        let state = self.states.last_mut().unwrap();
+        state.layer = Some(state.clip_count);
+        self.dt
+            .push_layer_with_blend(opacity.clamp(0.0, 1.0), blend_mode);
+    }
+
+    fn pop_layer(&mut self) -> Result<(), piet::Error> {
+        if self.states.last().unwrap().layer.is_none() {
+            return Err(piet::Error::StackUnbalance);
+        }
+
+        self.restore()
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.states.last_mut().unwrap().blend_mode = blend_mode;
+    }
+
+    fn set_global_alpha(&mut self, alpha: f32) {
+        if !alpha.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        self.states.last_mut().unwrap().alpha = alpha.clamp(0.0, 1.0);
+    }
+
+    fn set_antialias(&mut self, antialias: AntialiasMode) {
+        self.states.last_mut().unwrap().antialias = antialias;
+    }
+
+    fn fill_shadow(
+        &mut self,
+        shape: impl Shape,
+        blur_radius: f64,
+        offset: Vec2,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        if !self.check_shape(&shape) || !blur_radius.is_finite() || !offset.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let bounds = shape.bounding_box();
+        let brush = brush.make_brush(self, || bounds + offset).into_owned();
+        if blur_radius <= 0.0 {
+            self.fill(Affine::translate(offset) * shape.into_path(1e-3), &brush);
+            return;
+        }
+
+        let to_mask_space = self.blur_mask_space(blur_radius);
+        let scale = to_mask_space.determinant().abs().sqrt();
+        let Some((mask, mask_rect)) = blur::shape_mask(
+            &convert::to_path(shape),
+            bounds,
+            to_mask_space * Affine::translate(offset),
+            blur_radius * scale,
+            self.visible_mask_rect(to_mask_space),
+        ) else {
+            return;
+        };
+
+        self.fill_mask(&brush, &mask, mask_rect, to_mask_space);
+    }
+
+    fn blurred_rounded_rect(
+        &mut self,
+        rect: Rect,
+        radii: impl Into<RoundedRectRadii>,
+        blur_radius: f64,
+        brush: &impl IntoBrush<Self>,
+    ) {
+        let rounded_rect = RoundedRect::from_rect(rect, radii);
+        if !self.check_shape(&rounded_rect) || !blur_radius.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let brush = brush.make_brush(self, || rect).into_owned();
+        if blur_radius <= 0.0 {
+            self.fill(rounded_rect, &brush);
+            return;
+        }
+
+        let to_mask_space = self.blur_mask_space(blur_radius);
+        let scale = to_mask_space.determinant().abs().sqrt();
+
+        // The mask space only translates and scales, but it may flip the corners around.
+        let [a, _, _, d, _, _] = to_mask_space.as_coeffs();
+        let mut radii = rounded_rect.radii();
+        if a < 0.0 {
+            radii = RoundedRectRadii::new(
+                radii.top_right,
+                radii.top_left,
+                radii.bottom_left,
+                radii.bottom_right,
+            );
+        }
+        if d < 0.0 {
+            radii = RoundedRectRadii::new(
+                radii.bottom_left,
+                radii.bottom_right,
+                radii.top_right,
+                radii.top_left,
+            );
+        }
+        let radii = RoundedRectRadii::new(
+            radii.top_left * scale,
+            radii.top_right * scale,
+            radii.bottom_right * scale,
+            radii.bottom_left * scale,
+        );
+
+        let Some((mask, mask_rect)) = blur::rounded_rect_mask(
+            to_mask_space.transform_rect_bbox(rounded_rect.rect()),
+            radii,
+            blur_radius * scale,
+            self.visible_mask_rect(to_mask_space),
+        ) else {
+            return;
+        };
+
+        self.fill_mask(&brush, &mask, mask_rect, to_mask_space);
+    }
+
+    fn apply_filter(&mut self, rect: Rect, filter: &Filter) {
+        if !rect.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+        if !self.check_no_layer() {
+            return;
+        }
+
+        let width = self.dt.width() as usize;
+        let height = self.dt.height() as usize;
+        let bounds = Rect::new(0.0, 0.0, width as f64, height as f64);
+        let region = rect.round().intersect(bounds);
+        if region.is_empty() {
+            return;
+        }
+
+        if let Err(err) = filter.apply(self.dt.get_data_mut(), width, height, region) {
+            self.set_error(err);
+        }
+    }
+}
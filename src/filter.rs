@@ -0,0 +1,250 @@
+//! Filter effects that are applied to the pixels of a draw target.
+
+use piet::kurbo::Rect;
+
+use crate::blur;
+
+/// A filter effect for [`RenderContextExt::apply_filter`](crate::RenderContextExt::apply_filter).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Blurs the pixels. The radius has the same meaning as in
+    /// [`RenderContext::blurred_rect`](piet::RenderContext::blurred_rect).
+    GaussianBlur(f64),
+
+    /// Transforms colors with a 4×5 matrix, in row-major order.
+    ///
+    /// Like SVG's `feColorMatrix`, the rows compute the red, green, blue and alpha components from
+    /// the unpremultiplied components of the original color, followed by a constant 1. Components
+    /// range from 0 to 1.
+    ColorMatrix([f32; 20]),
+
+    /// Convolves the pixels with a kernel of `width` by `height` weights, in row-major order.
+    ///
+    /// Both dimensions must be odd. The weight at the center of the kernel applies to the pixel
+    /// itself. Like SVG's `feConvolveMatrix`, the kernel is rotated by 180° before it is applied,
+    /// so the weight right of the center applies to the pixel on the left.
+    Convolve {
+        width: usize,
+        height: usize,
+        kernel: Vec<f32>,
+    },
+}
+
+/// The weights of the red, green and blue components in the luminance of a color.
+const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+impl Filter {
+    /// Returns a color matrix that converts colors to grayscale.
+    pub fn grayscale() -> Self {
+        let [r, g, b] = LUMINANCE;
+        #[rustfmt::skip]
+        let matrix = [
+            r,   g,   b,   0.0, 0.0,
+            r,   g,   b,   0.0, 0.0,
+            r,   g,   b,   0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+
+    /// Returns a color matrix that gives colors a sepia tone.
+    pub fn sepia() -> Self {
+        #[rustfmt::skip]
+        let matrix = [
+            0.393, 0.769, 0.189, 0.0, 0.0,
+            0.349, 0.686, 0.168, 0.0, 0.0,
+            0.272, 0.534, 0.131, 0.0, 0.0,
+            0.0,   0.0,   0.0,   1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+
+    /// Returns a color matrix that scales the saturation of colors by `amount`.
+    ///
+    /// An amount of 0 produces grayscale, and 1 leaves colors unchanged.
+    pub fn saturate(amount: f32) -> Self {
+        let s = amount;
+        let [r, g, b] = LUMINANCE;
+        #[rustfmt::skip]
+        let matrix = [
+            r + (1.0 - r) * s, g - g * s,         b - b * s,         0.0, 0.0,
+            r - r * s,         g + (1.0 - g) * s, b - b * s,         0.0, 0.0,
+            r - r * s,         g - g * s,         b + (1.0 - b) * s, 0.0, 0.0,
+            0.0,               0.0,               0.0,               1.0, 0.0,
+        ];
+        Filter::ColorMatrix(matrix)
+    }
+
+    /// Applies the filter to the pixels of an image within `region`, which must be pixel-aligned
+    /// and inside the image.
+    ///
+    /// Pixels outside of the region may be read, but aren't modified. Returns an error if the
+    /// filter is invalid.
+    pub(crate) fn apply(
+        &self,
+        data: &mut [u32],
+        width: usize,
+        height: usize,
+        region: Rect,
+    ) -> Result<(), piet::Error> {
+        let image = Pixels {
+            width,
+            height,
+            region: (
+                region.x0 as usize,
+                region.y0 as usize,
+                region.x1 as usize,
+                region.y1 as usize,
+            ),
+        };
+
+        match self {
+            Filter::GaussianBlur(blur_radius) => {
+                if !blur_radius.is_finite() {
+                    return Err(piet::Error::InvalidInput);
+                }
+                if *blur_radius > 0.0 {
+                    image.gaussian_blur(data, *blur_radius);
+                }
+            }
+            Filter::ColorMatrix(matrix) => {
+                if !matrix.iter().all(|v| v.is_finite()) {
+                    return Err(piet::Error::InvalidInput);
+                }
+                image.color_matrix(data, matrix);
+            }
+            Filter::Convolve {
+                width,
+                height,
+                kernel,
+            } => {
+                if width % 2 == 0
+                    || height % 2 == 0
+                    || width.checked_mul(*height) != Some(kernel.len())
+                    || !kernel.iter().all(|v| v.is_finite())
+                {
+                    return Err(piet::Error::InvalidInput);
+                }
+                image.convolve(data, (*width, *height), kernel);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The dimensions of an image being filtered, and the region of it to filter.
+struct Pixels {
+    width: usize,
+    height: usize,
+
+    /// The left, top, right and bottom edges of the region.
+    region: (usize, usize, usize, usize),
+}
+
+impl Pixels {
+    fn gaussian_blur(&self, data: &mut [u32], blur_radius: f64) {
+        let kernel = blur::kernel(blur_radius, self.width.max(self.height));
+        let half = kernel.len() / 2;
+        let (x0, y0, x1, y1) = self.region;
+        let region_width = x1 - x0;
+
+        // Blur horizontally, for every row that the vertical pass reads.
+        let rows = y0.saturating_sub(half)..(y1 + half).min(self.height);
+        let mut horizontal = Vec::with_capacity(rows.len() * region_width);
+        for y in rows.clone() {
+            let row = &data[y * self.width..(y + 1) * self.width];
+            horizontal.extend((x0..x1).map(|x| {
+                weighted_sum(kernel.iter().enumerate().map(|(i, &weight)| {
+                    let x = (x + i).saturating_sub(half).min(self.width - 1);
+                    (weight, unpack(row[x]))
+                }))
+            }));
+        }
+
+        // Blur vertically, writing the result back.
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let sum = weighted_sum(kernel.iter().enumerate().map(|(i, &weight)| {
+                    let y = (y + i).saturating_sub(half).clamp(rows.start, rows.end - 1);
+                    (weight, horizontal[(y - rows.start) * region_width + x - x0])
+                }));
+                data[y * self.width + x] = pack(sum);
+            }
+        }
+    }
+
+    fn color_matrix(&self, data: &mut [u32], matrix: &[f32; 20]) {
+        let (x0, y0, x1, y1) = self.region;
+        for row in data.chunks_exact_mut(self.width).take(y1).skip(y0) {
+            for pixel in &mut row[x0..x1] {
+                let [a, r, g, b] = unpack(*pixel).map(|c| c / 255.0);
+                let input = if a > 0.0 {
+                    [r / a, g / a, b / a, a, 1.0]
+                } else {
+                    [0.0, 0.0, 0.0, 0.0, 1.0]
+                };
+
+                let [r, g, b, a] = [0, 1, 2, 3].map(|i| {
+                    let row = &matrix[i * 5..(i + 1) * 5];
+                    let value: f32 = row.iter().zip(input).map(|(m, c)| m * c).sum();
+                    value.clamp(0.0, 1.0)
+                });
+                *pixel = pack([a, r * a, g * a, b * a].map(|c| c * 255.0));
+            }
+        }
+    }
+
+    fn convolve(&self, data: &mut [u32], (width, height): (usize, usize), kernel: &[f32]) {
+        let (x0, y0, x1, y1) = self.region;
+        let (half_width, half_height) = (width / 2, height / 2);
+
+        // Copy the pixels that the kernel reads, since the region is overwritten as it goes.
+        let columns = x0.saturating_sub(half_width)..(x1 + half_width).min(self.width);
+        let rows = y0.saturating_sub(half_height)..(y1 + half_height).min(self.height);
+        let source: Vec<[f32; 4]> = rows
+            .clone()
+            .flat_map(|y| data[y * self.width..][columns.clone()].iter())
+            .map(|&pixel| unpack(pixel))
+            .collect();
+
+        // The kernel is rotated, so its last weight applies to the top-left pixel.
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let sum = weighted_sum(kernel.iter().rev().enumerate().map(|(i, &weight)| {
+                    let sx = (x + i % width)
+                        .saturating_sub(half_width)
+                        .clamp(columns.start, columns.end - 1);
+                    let sy = (y + i / width)
+                        .saturating_sub(half_height)
+                        .clamp(rows.start, rows.end - 1);
+                    let index = (sy - rows.start) * columns.len() + sx - columns.start;
+                    (weight, source[index])
+                }));
+                data[y * self.width + x] = pack(sum);
+            }
+        }
+    }
+}
+
+/// Sums pixels with their weights.
+fn weighted_sum(pixels: impl Iterator<Item = (f32, [f32; 4])>) -> [f32; 4] {
+    pixels.fold([0.0; 4], |mut sum, (weight, pixel)| {
+        for (sum, channel) in sum.iter_mut().zip(pixel) {
+            *sum += weight * channel;
+        }
+        sum
+    })
+}
+
+/// Splits a premultiplied pixel into its alpha, red, green and blue channels.
+fn unpack(pixel: u32) -> [f32; 4] {
+    pixel.to_be_bytes().map(f32::from)
+}
+
+/// Combines alpha, red, green and blue channels into a premultiplied pixel, clamping them to a
+/// valid range.
+fn pack(channels: [f32; 4]) -> u32 {
+    let alpha = channels[0].round().clamp(0.0, 255.0);
+    u32::from_be_bytes(channels.map(|c| c.round().clamp(0.0, alpha) as u8))
+}
@@ -25,16 +25,23 @@ impl RaqoteImage {
     }
 
     /// Create a new image from a subregion of another image (or the draw target).
-    pub(crate) fn from_region(src: impl AsImage, src_rect: impl Into<Rect>) -> Self {
+    ///
+    /// The region is rounded to whole pixels and clamped to the bounds of the source. Returns an
+    /// error if the resulting region is empty.
+    pub(crate) fn from_region(
+        src: impl AsImage,
+        src_rect: impl Into<Rect>,
+    ) -> Result<Self, piet::Error> {
         let src_image = src.as_image();
-        let src_rect = src_rect.into();
+        let bounds = Size::new(src_image.width.into(), src_image.height.into());
+        let src_rect = clamp_region(src_rect.into(), bounds)?;
 
-        let src_x = src_rect.origin().x as usize;
-        let src_y = src_rect.origin().y as usize;
+        let src_x = src_rect.x0 as usize;
+        let src_y = src_rect.y0 as usize;
         let src_width = src_rect.width() as usize;
         let src_height = src_rect.height() as usize;
 
-        let mut output = vec![0u32; src_rect.area() as usize];
+        let mut output = vec![0u32; src_width * src_height];
 
         for (i, row) in src_image
             .data
@@ -49,8 +56,85 @@ impl RaqoteImage {
             dst_row_slice.copy_from_slice(src_row_slice);
         }
 
-        RaqoteImage::new(src_width as i32, src_height as i32, output)
+        Ok(RaqoteImage::new(
+            src_width as i32,
+            src_height as i32,
+            output,
+        ))
     }
+
+    /// Creates an image twice the size of this one, with reflected copies to the right and below.
+    ///
+    /// Repeating the result reflects the original image.
+    pub(crate) fn reflected(&self) -> Self {
+        let OwnedImage {
+            ref data,
+            width,
+            height,
+        } = self.0;
+        let (width, height) = (width as usize, height as usize);
+
+        let mut output = Vec::with_capacity(4 * data.len());
+        for y in (0..height).chain((0..height).rev()) {
+            let row = &data[y * width..(y + 1) * width];
+            output.extend_from_slice(row);
+            output.extend(row.iter().rev());
+        }
+
+        RaqoteImage::new(2 * width as i32, 2 * height as i32, output)
+    }
+
+    /// Creates a copy of this image surrounded by a one pixel wide transparent border.
+    pub(crate) fn with_transparent_border(&self) -> Self {
+        let OwnedImage {
+            ref data,
+            width,
+            height,
+        } = self.0;
+        let padded_width = width as usize + 2;
+
+        let mut output = vec![0; padded_width * (height as usize + 2)];
+        for (src_row, dst_row) in data
+            .chunks_exact(width as usize)
+            .zip(output.chunks_exact_mut(padded_width).skip(1))
+        {
+            dst_row[1..padded_width - 1].copy_from_slice(src_row);
+        }
+
+        RaqoteImage::new(width + 2, height + 2, output)
+    }
+}
+
+/// How an image brush fills the area outside of its image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageExtend {
+    /// Extends the edge pixels of the image.
+    Pad,
+
+    /// Tiles the image.
+    Repeat,
+
+    /// Tiles the image, mirroring every other copy.
+    Reflect,
+
+    /// Leaves the area outside of the image transparent.
+    None,
+}
+
+/// Rounds `rect` to whole pixels and intersects it with an image of the given size.
+///
+/// Returns an error if `rect` is not finite or doesn't overlap the image.
+pub(crate) fn clamp_region(rect: Rect, size: Size) -> Result<Rect, piet::Error> {
+    if !rect.is_finite() {
+        return Err(piet::Error::InvalidInput);
+    }
+
+    let region = rect.abs().round().intersect(size.to_rect());
+    if region.is_empty() {
+        return Err(piet::Error::InvalidInput);
+    }
+
+    Ok(region)
 }
 
 impl Image for RaqoteImage {
@@ -61,11 +145,11 @@ impl Image for RaqoteImage {
 
 /// Image-like types.
 pub(crate) trait AsImage {
-    fn as_image(&self) -> raqote::Image;
+    fn as_image(&self) -> raqote::Image<'_>;
 }
 
 impl AsImage for RaqoteImage {
-    fn as_image(&self) -> raqote::Image {
+    fn as_image(&self) -> raqote::Image<'_> {
         raqote::Image {
             width: self.0.width,
             height: self.0.height,
@@ -78,7 +162,7 @@ impl<B> AsImage for DrawTarget<B>
 where
     B: AsRef<[u32]> + AsMut<[u32]>,
 {
-    fn as_image(&self) -> raqote::Image {
+    fn as_image(&self) -> raqote::Image<'_> {
         raqote::Image {
             width: self.width(),
             height: self.height(),
@@ -88,7 +172,7 @@ where
 }
 
 impl<T: AsImage> AsImage for &T {
-    fn as_image(&self) -> raqote::Image {
+    fn as_image(&self) -> raqote::Image<'_> {
         (**self).as_image()
     }
 }
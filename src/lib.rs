@@ -9,30 +9,40 @@ use std::borrow::Cow;
 
 use image::RaqoteImage;
 use piet::{
-    FixedGradient, Image, IntoBrush, RenderContext,
+    FixedGradient, FixedLinearGradient, FixedRadialGradient, Image, IntoBrush, RenderContext,
     kurbo::{self, Affine, Rect},
 };
 use piet_cosmic_text::cosmic_text::{self, SwashCache};
 use raqote::{
-    DrawOptions, DrawTarget, Gradient, Mask, SolidSource, Source, Spread, StrokeStyle, Transform,
-    Winding,
+    AntialiasMode, BlendMode, DrawOptions, DrawTarget, ExtendMode, FilterMode, Gradient, Mask,
+    PathBuilder, Point, SolidSource, Source, Spread, StrokeStyle, Transform, Winding,
 };
 use tinyvec::tiny_vec;
 
-use crate::image::AsImage;
+use crate::{image::AsImage, text::GlyphPathCache};
 
+mod blur;
 mod convert;
+mod ext;
+mod filter;
 mod image;
 mod text;
 
 use tinyvec::TinyVec;
 
+pub use ext::RenderContextExt;
+pub use filter::Filter;
+pub use image::ImageExtend;
 pub use raqote;
+pub use text::GlyphCacheStats;
 
 pub struct RaqoteRenderContext<'dt, 'cache, B = Vec<u32>> {
     dt: &'dt mut DrawTarget<B>,
     cache: &'cache mut Cache,
     states: TinyVec<[ContextState; 1]>,
+
+    /// The first error that occurred since the last call to `status`.
+    error: Result<(), piet::Error>,
 }
 
 impl<'dt, 'cache, B> RaqoteRenderContext<'dt, 'cache, B> {
@@ -41,6 +51,7 @@ impl<'dt, 'cache, B> RaqoteRenderContext<'dt, 'cache, B> {
             dt,
             cache,
             states: tiny_vec![[ContextState; 1] => ContextState::default()],
+            error: Ok(()),
         }
     }
 }
@@ -53,27 +64,226 @@ enum BrushInner {
     Solid(SolidSource),
     LinearGradient(Gradient, Spread, Transform),
     RadialGradient(Gradient, Spread, Transform),
+    TwoCircleRadialGradient(Gradient, Spread, Point, f32, Point, f32, Transform),
+    SweepGradient(Gradient, Spread, f32, f32, Transform),
+    Image(RaqoteImage, ExtendMode, FilterMode, Transform),
 }
 
 impl Brush {
-    fn into_source<'a>(self) -> Source<'a> {
-        match self.0 {
-            BrushInner::Solid(solid_source) => Source::Solid(solid_source),
+    /// Creates a brush that fills shapes with `image`.
+    ///
+    /// `transform` maps the pixel coordinates of the image into user space, and `extend`
+    /// determines what is drawn outside of the image. Returns an error if the image is empty or
+    /// `transform` can't be inverted.
+    pub fn image(
+        image: &RaqoteImage,
+        transform: Affine,
+        extend: ImageExtend,
+        interp: piet::InterpolationMode,
+    ) -> Result<Self, piet::Error> {
+        let to_image = transform.inverse();
+        if image.size().is_empty() || !to_image.is_finite() {
+            return Err(piet::Error::InvalidInput);
+        }
+
+        let (image, extend_mode, to_image) = match extend {
+            ImageExtend::Pad => (image.clone(), ExtendMode::Pad, to_image),
+            ImageExtend::Repeat => (image.clone(), ExtendMode::Repeat, to_image),
+            // raqote can't reflect images, so repeat an image that contains the reflections.
+            ImageExtend::Reflect => (image.reflected(), ExtendMode::Repeat, to_image),
+            // Pad with a transparent border instead, which is one pixel in from the image.
+            ImageExtend::None => (
+                image.with_transparent_border(),
+                ExtendMode::Pad,
+                Affine::translate((1.0, 1.0)) * to_image,
+            ),
+        };
+
+        Ok(Brush(BrushInner::Image(
+            image,
+            extend_mode,
+            convert::to_filter_mode(interp),
+            convert::to_transform(to_image),
+        )))
+    }
+
+    /// Creates a linear gradient brush that fills the area beyond its end points according to
+    /// `spread`.
+    ///
+    /// [`RenderContext::gradient`] creates gradients with [`Spread::Pad`]. Returns an error if the
+    /// end points or stops aren't finite, or if there are no stops.
+    pub fn linear_gradient_with_spread(
+        gradient: FixedLinearGradient,
+        spread: Spread,
+    ) -> Result<Self, piet::Error> {
+        if !gradient.start.is_finite() || !gradient.end.is_finite() {
+            return Err(piet::Error::InvalidInput);
+        }
+
+        let source = Source::new_linear_gradient(
+            gradient_stops(gradient.stops)?,
+            convert::to_point(gradient.start),
+            convert::to_point(gradient.end),
+            spread,
+        );
+
+        match source {
+            Source::LinearGradient(gradient, spread, transform) => Ok(Brush(
+                BrushInner::LinearGradient(gradient, spread, transform),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Creates a radial gradient brush that fills the area beyond its radius according to
+    /// `spread`.
+    ///
+    /// [`RenderContext::gradient`] creates gradients with [`Spread::Pad`]. Returns an error if the
+    /// circle, origin offset or stops aren't finite, or if there are no stops.
+    pub fn radial_gradient_with_spread(
+        gradient: FixedRadialGradient,
+        spread: Spread,
+    ) -> Result<Self, piet::Error> {
+        if !gradient.center.is_finite()
+            || !gradient.origin_offset.is_finite()
+            || !gradient.radius.is_finite()
+        {
+            return Err(piet::Error::InvalidInput);
+        }
+
+        let center = convert::to_point(gradient.center);
+        let radius = gradient.radius as f32;
+        let stops = gradient_stops(gradient.stops)?;
+
+        // Like piet-cairo, an origin offset moves the focal point of the gradient: a zero-radius
+        // circle at the offset origin, expanding to the outer circle.
+        let source = if gradient.origin_offset == kurbo::Vec2::ZERO {
+            Source::new_radial_gradient(stops, center, radius, spread)
+        } else {
+            let origin = convert::to_point(gradient.center + gradient.origin_offset);
+            Source::new_two_circle_radial_gradient(stops, origin, 0.0, center, radius, spread)
+        };
+
+        match source {
+            Source::RadialGradient(gradient, spread, transform) => Ok(Brush(
+                BrushInner::RadialGradient(gradient, spread, transform),
+            )),
+            Source::TwoCircleRadialGradient(
+                gradient,
+                spread,
+                center1,
+                radius1,
+                center2,
+                radius2,
+                transform,
+            ) => Ok(Brush(BrushInner::TwoCircleRadialGradient(
+                gradient, spread, center1, radius1, center2, radius2, transform,
+            ))),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Creates a sweep (or conic) gradient brush, with colors that change with the angle around
+    /// `center`.
+    ///
+    /// Angles are in radians, and increase in the same direction as [`Affine::rotate`]. The stops
+    /// are placed from `start_angle` to `end_angle`, and the rest of the circle is filled
+    /// according to `spread`. Returns an error if the center, angles or stops aren't finite, or
+    /// if there are no stops.
+    pub fn sweep_gradient(
+        center: impl Into<kurbo::Point>,
+        start_angle: f64,
+        end_angle: f64,
+        stops: impl piet::GradientStops,
+        spread: Spread,
+    ) -> Result<Self, piet::Error> {
+        let center = center.into();
+        if !center.is_finite() || !start_angle.is_finite() || !end_angle.is_finite() {
+            return Err(piet::Error::InvalidInput);
+        }
+
+        // raqote only maps angles to stops correctly when the gradient starts at zero, so rotate
+        // the start angle onto the x-axis instead, and mirror gradients that go backwards.
+        let sweep = end_angle - start_angle;
+        let to_gradient = Affine::scale_non_uniform(1.0, sweep.signum())
+            * Affine::rotate(-start_angle)
+            * Affine::translate(-center.to_vec2());
+
+        Ok(Brush(BrushInner::SweepGradient(
+            gradient_stops(stops)?,
+            spread,
+            0.0,
+            sweep.abs().to_degrees() as f32,
+            convert::to_transform(to_gradient),
+        )))
+    }
+
+    /// Returns the raqote source that draws with this brush.
+    ///
+    /// The transforms stored in the brush map user space into the space of the gradient or image.
+    /// Drawing operations set the draw target's transform to the user-to-device transform, which
+    /// raqote combines with the source transform, so brushes follow the current transform without
+    /// being adjusted here.
+    fn to_source(&self) -> Source<'_> {
+        match &self.0 {
+            BrushInner::Solid(solid_source) => Source::Solid(*solid_source),
             BrushInner::LinearGradient(gradient, spread, transform) => {
-                Source::LinearGradient(gradient, spread, transform)
+                Source::LinearGradient(gradient.clone(), *spread, *transform)
             }
             BrushInner::RadialGradient(gradient, spread, transform) => {
-                Source::RadialGradient(gradient, spread, transform)
+                Source::RadialGradient(gradient.clone(), *spread, *transform)
+            }
+            BrushInner::TwoCircleRadialGradient(
+                gradient,
+                spread,
+                center1,
+                radius1,
+                center2,
+                radius2,
+                transform,
+            ) => Source::TwoCircleRadialGradient(
+                gradient.clone(),
+                *spread,
+                *center1,
+                *radius1,
+                *center2,
+                *radius2,
+                *transform,
+            ),
+            BrushInner::SweepGradient(gradient, spread, start_angle, end_angle, transform) => {
+                Source::SweepGradient(
+                    gradient.clone(),
+                    *spread,
+                    *start_angle,
+                    *end_angle,
+                    *transform,
+                )
+            }
+            BrushInner::Image(image, extend_mode, filter_mode, transform) => {
+                Source::Image(image.as_image(), *extend_mode, *filter_mode, *transform)
             }
         }
     }
 }
 
+/// Converts gradient stops, returning an error if there are none or their positions aren't
+/// finite.
+fn gradient_stops(stops: impl piet::GradientStops) -> Result<Gradient, piet::Error> {
+    let stops = convert::to_stops(stops);
+    if stops.is_empty() || !stops.iter().all(|stop| stop.position.is_finite()) {
+        return Err(piet::Error::InvalidInput);
+    }
+
+    Ok(Gradient { stops })
+}
+
 #[derive(Default)]
 pub struct Cache {
     text: piet_cosmic_text::Text,
 
     glyph_cache: Option<SwashCache>,
+
+    glyph_paths: GlyphPathCache,
 }
 
 impl Cache {
@@ -81,6 +291,189 @@ impl Cache {
         Cache {
             text: piet_cosmic_text::Text::new(),
             glyph_cache: None,
+            glyph_paths: GlyphPathCache::default(),
+        }
+    }
+
+    /// Sets the maximum number of glyph outlines to keep converted to paths.
+    ///
+    /// The least recently used paths are evicted once the limit is reached. At least one path is
+    /// always kept.
+    pub fn set_glyph_path_limit(&mut self, limit: usize) {
+        self.glyph_paths.set_limit(limit);
+    }
+
+    /// Returns hit and miss counts for the glyph path cache.
+    pub fn glyph_path_stats(&self) -> GlyphCacheStats {
+        self.glyph_paths.stats()
+    }
+}
+
+impl<B> RaqoteRenderContext<'_, '_, B>
+where
+    B: AsRef<[u32]> + AsMut<[u32]>,
+{
+    /// Returns the transform from user space to device space: the current piet transform, followed
+    /// by the transform that was set on the draw target.
+    fn device_transform(&self) -> Affine {
+        convert::from_transform(*self.dt.get_transform()) * self.current_transform()
+    }
+
+    /// Runs `f` with the draw target's transform set to the current piet transform, composed with
+    /// the transform that was already set on the draw target.
+    fn with_transform<R>(&mut self, f: impl FnOnce(&mut DrawTarget<B>) -> R) -> R {
+        self.with_local_transform(Affine::IDENTITY, f)
+    }
+
+    /// Like [`Self::with_transform`], but applies `local` before the current transform.
+    pub(crate) fn with_local_transform<R>(
+        &mut self,
+        local: Affine,
+        f: impl FnOnce(&mut DrawTarget<B>) -> R,
+    ) -> R {
+        let base = *self.dt.get_transform();
+        let transform = convert::to_transform(self.device_transform() * local);
+
+        self.dt.set_transform(&transform);
+        let result = f(self.dt);
+        self.dt.set_transform(&base);
+
+        result
+    }
+
+    /// Returns the options for drawing with the current state.
+    fn draw_options(&self) -> DrawOptions {
+        let state = self.states.last().unwrap();
+        DrawOptions {
+            blend_mode: state.blend_mode,
+            alpha: state.alpha,
+            antialias: state.antialias,
+        }
+    }
+
+    /// Returns a transform from user space into a space for computing blur masks in.
+    ///
+    /// The space has the same resolution as the device, and blur radii are equal in both of its
+    /// directions.
+    fn blur_space(&self) -> Affine {
+        let device = self.device_transform();
+        let [a, b, c, d, _, _] = device.as_coeffs();
+        if b == 0.0 && c == 0.0 && a.abs() == d.abs() {
+            // The transform only translates and uniformly scales, so the mask can be computed
+            // directly in device space.
+            device
+        } else {
+            // Compute the mask in an unrotated copy of user space, and let the image source
+            // handle rotation and skew.
+            Affine::scale(device.determinant().abs().sqrt())
+        }
+    }
+
+    /// Returns a transform from user space into a space for computing the mask of a blur with
+    /// `blur_radius` in.
+    ///
+    /// This is the [blur space](Self::blur_space), unless the blur is so wide that a lower
+    /// resolution can't be told apart from it, which bounds the cost of computing the mask.
+    fn blur_mask_space(&self, blur_radius: f64) -> Affine {
+        let blur_space = self.blur_space();
+        let scale = blur_space.determinant().abs().sqrt();
+        let resolution = (blur::MAX_MASK_BLUR_RADIUS / (blur_radius * scale)).min(1.0);
+        Affine::scale(resolution) * blur_space
+    }
+
+    /// Returns the bounding box of the draw target in the space that `to_mask_space` maps user
+    /// space into.
+    ///
+    /// Masks only need to cover this area, since nothing outside of it can be seen.
+    fn visible_mask_rect(&self, to_mask_space: Affine) -> Rect {
+        let bounds = Rect::new(0.0, 0.0, self.dt.width().into(), self.dt.height().into());
+        (to_mask_space * self.device_transform().inverse()).transform_rect_bbox(bounds)
+    }
+
+    /// Draws `brush` through `mask`, which covers the pixel-aligned `mask_rect` in the space that
+    /// `to_mask_space` maps user space into.
+    fn fill_mask(&mut self, brush: &Brush, mask: &Mask, mask_rect: Rect, to_mask_space: Affine) {
+        // Only fill the visible part of the mask, which may be scaled up a long way.
+        let visible = self.visible_mask_rect(to_mask_space).inflate(1.0, 1.0);
+        let fill_rect = mask_rect.intersect(visible);
+        if fill_rect.width() <= 0.0 || fill_rect.height() <= 0.0 {
+            return;
+        }
+
+        // Render the brush through the mask into an intermediate target. `DrawTarget::mask` only
+        // places masks correctly at the origin, so the mask is drawn at the origin of a target
+        // that has the same size.
+        let to_pixels =
+            convert::to_transform(Affine::translate(-mask_rect.origin().to_vec2()) * to_mask_space);
+        let mut masked = DrawTarget::new(mask.width, mask.height);
+        masked.set_transform(&to_pixels);
+        masked.mask(&brush.to_source(), 0, 0, mask);
+
+        let image = Source::Image(
+            masked.as_image(),
+            ExtendMode::Pad,
+            FilterMode::Bilinear,
+            to_pixels,
+        );
+        let user_rect = to_mask_space.inverse().transform_rect_bbox(fill_rect);
+        let options = self.draw_options();
+
+        self.with_transform(|dt| {
+            dt.fill_rect(
+                user_rect.x0 as f32,
+                user_rect.y0 as f32,
+                user_rect.width() as f32,
+                user_rect.height() as f32,
+                &image,
+                &options,
+            );
+        });
+    }
+
+    /// Records `error`, unless an earlier error is still pending.
+    fn set_error(&mut self, error: piet::Error) {
+        if self.error.is_ok() {
+            self.error = Err(error);
+        }
+    }
+
+    /// Returns whether `shape` is made up of finite coordinates, recording an error if it isn't.
+    fn check_shape(&mut self, shape: &impl kurbo::Shape) -> bool {
+        let is_finite = shape.path_elements(0.1).all(|el| el.is_finite());
+        if !is_finite {
+            self.set_error(piet::Error::InvalidInput);
+        }
+        is_finite
+    }
+
+    /// Returns whether no layer is open, recording an error if one is.
+    ///
+    /// Operations that read or write the pixels of the draw target directly can't be used in
+    /// layers, since a layer only reaches those pixels when it is popped.
+    fn check_no_layer(&mut self) -> bool {
+        let no_layer = self.states.iter().all(|state| state.layer.is_none());
+        if !no_layer {
+            self.set_error(piet::Error::NotSupported);
+        }
+        no_layer
+    }
+
+    fn pop_clips(&mut self, count: usize) {
+        for _ in 0..count {
+            self.dt.pop_clip();
+        }
+    }
+
+    /// Pops the clips and layer that were pushed onto the draw target while `state` was active.
+    fn unwind(&mut self, state: &mut ContextState) {
+        let clip_count = std::mem::take(&mut state.clip_count);
+        match state.layer.take() {
+            Some(layer_clips) => {
+                self.pop_clips(clip_count - layer_clips);
+                self.dt.pop_layer();
+                self.pop_clips(layer_clips);
+            }
+            None => self.pop_clips(clip_count),
         }
     }
 }
@@ -95,25 +488,40 @@ where
     type TextLayout = piet_cosmic_text::TextLayout;
 
     fn status(&mut self) -> Result<(), piet::Error> {
-        Ok(())
+        std::mem::replace(&mut self.error, Ok(()))
     }
 
     fn clear(&mut self, region: impl Into<Option<Rect>>, color: piet::Color) {
-        let region = region.into().unwrap_or_else(|| {
-            Rect::new(0.0, 0.0, self.dt.width().into(), self.dt.height().into())
-        });
+        if !self.check_no_layer() {
+            return;
+        }
 
-        let (x, y) = (region.origin().x, region.origin().y);
-        let (width, height) = (region.size().width, region.size().height);
-        let source = Source::Solid(convert::to_color(color).into());
-        self.dt.fill_rect(
-            x as f32,
-            y as f32,
-            width as f32,
-            height as f32,
-            &source,
-            &DrawOptions::new(),
-        );
+        let bounds = Rect::new(0.0, 0.0, self.dt.width().into(), self.dt.height().into());
+        let region = region.into().unwrap_or(bounds);
+        if !region.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let region = region.round().intersect(bounds);
+        if region.is_empty() {
+            return;
+        }
+
+        // Write the pixels directly, since `clear` ignores the transform and clip and replaces
+        // the existing contents instead of blending with them.
+        let pixel = SolidSource::from(convert::to_color(color)).to_u32();
+        let width = self.dt.width() as usize;
+        let (x0, x1) = (region.x0 as usize, region.x1 as usize);
+        for row in self
+            .dt
+            .get_data_mut()
+            .chunks_exact_mut(width)
+            .take(region.y1 as usize)
+            .skip(region.y0 as usize)
+        {
+            row[x0..x1].fill(pixel);
+        }
     }
 
     fn solid_brush(&mut self, color: piet::Color) -> Self::Brush {
@@ -127,91 +535,88 @@ where
         &mut self,
         gradient: impl Into<piet::FixedGradient>,
     ) -> Result<Self::Brush, piet::Error> {
-        let inner = match gradient.into() {
+        match gradient.into() {
             FixedGradient::Linear(linear) => {
-                let start = convert::to_point(linear.start);
-                let end = convert::to_point(linear.end);
-
-                let source = Source::new_linear_gradient(
-                    Gradient {
-                        stops: convert::to_stops(linear.stops),
-                    },
-                    start,
-                    end,
-                    Spread::Pad,
-                );
-
-                match source {
-                    Source::LinearGradient(gradient, spread, transform) => {
-                        BrushInner::LinearGradient(gradient, spread, transform)
-                    }
-                    _ => unreachable!(),
-                }
+                Brush::linear_gradient_with_spread(linear, Spread::Pad)
             }
             FixedGradient::Radial(radial) => {
-                let center = convert::to_point(radial.center);
-
-                let source = Source::new_radial_gradient(
-                    Gradient {
-                        stops: convert::to_stops(radial.stops),
-                    },
-                    center,
-                    radial.radius as f32,
-                    Spread::Pad,
-                );
-
-                match source {
-                    Source::RadialGradient(gradient, spread, transform) => {
-                        BrushInner::RadialGradient(gradient, spread, transform)
-                    }
-                    _ => unreachable!(),
-                }
+                Brush::radial_gradient_with_spread(radial, Spread::Pad)
             }
-        };
-
-        Ok(Brush(inner))
+        }
     }
 
     fn fill(&mut self, shape: impl kurbo::Shape, brush: &impl IntoBrush<Self>) {
+        if !self.check_shape(&shape) {
+            return;
+        }
+
         let brush = brush.make_brush(self, || shape.bounding_box());
 
         let mut path = convert::to_path(shape);
         path.winding = Winding::NonZero;
 
-        self.dt.fill(
-            &path,
-            &brush.into_owned().into_source(),
-            &DrawOptions::new(),
-        );
+        let source = brush.to_source();
+        let options = self.draw_options();
+        self.with_transform(|dt| dt.fill(&path, &source, &options));
     }
 
     fn fill_even_odd(&mut self, shape: impl kurbo::Shape, brush: &impl IntoBrush<Self>) {
+        if !self.check_shape(&shape) {
+            return;
+        }
+
         let brush = brush.make_brush(self, || shape.bounding_box());
 
         let mut path = convert::to_path(shape);
         path.winding = Winding::EvenOdd;
 
-        self.dt.fill(
-            &path,
-            &brush.into_owned().into_source(),
-            &DrawOptions::new(),
-        );
+        let source = brush.to_source();
+        let options = self.draw_options();
+        self.with_transform(|dt| dt.fill(&path, &source, &options));
     }
 
     fn clip(&mut self, shape: impl kurbo::Shape) {
+        if !self.check_shape(&shape) {
+            return;
+        }
+
+        let bounds = shape.bounding_box();
         let path = convert::to_path(shape);
-        self.dt.push_clip(&path);
+        match self.states.last().unwrap().antialias {
+            AntialiasMode::Gray => self.with_transform(|dt| dt.push_clip(&path)),
+            AntialiasMode::None => {
+                let device = self.device_transform();
+                let path = aliased_path(
+                    &path,
+                    device.transform_rect_bbox(bounds),
+                    device,
+                    (self.dt.width(), self.dt.height()),
+                );
+
+                let base = *self.dt.get_transform();
+                self.dt.set_transform(&Transform::identity());
+                self.dt.push_clip(&path);
+                self.dt.set_transform(&base);
+            }
+        }
+        self.states.last_mut().unwrap().clip_count += 1;
     }
 
     fn stroke(&mut self, shape: impl kurbo::Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        if !self.check_shape(&shape) || !width.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
         let brush = brush.make_brush(self, || shape.bounding_box());
         let path = convert::to_path(shape);
-        let source = brush.into_owned().into_source();
+        let source = brush.to_source();
         let style = StrokeStyle {
             width: width as f32,
             ..Default::default()
         };
-        self.dt.stroke(&path, &source, &style, &DrawOptions::new());
+        let options = self.draw_options();
+        self.with_transform(|dt| dt.stroke(&path, &source, &style, &options));
     }
 
     fn stroke_styled(
@@ -221,11 +626,17 @@ where
         width: f64,
         style: &piet::StrokeStyle,
     ) {
+        if !self.check_shape(&shape) || !width.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
         let brush = brush.make_brush(self, || shape.bounding_box());
         let path = convert::to_path(shape);
-        let source = brush.into_owned().into_source();
+        let source = brush.to_source();
         let style = convert::to_stroke_style(width, style);
-        self.dt.stroke(&path, &source, &style, &DrawOptions::new());
+        let options = self.draw_options();
+        self.with_transform(|dt| dt.stroke(&path, &source, &style, &options));
     }
 
     fn text(&mut self) -> &mut Self::Text {
@@ -234,7 +645,13 @@ where
 
     fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<kurbo::Point>) {
         let pos = pos.into();
+        if !pos.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
         let mut line_processor = piet_cosmic_text::LineProcessor::new();
+        let scale = text::text_scale(self.device_transform());
 
         for run in layout.layout_runs() {
             for glyph in run.glyphs {
@@ -245,17 +662,24 @@ where
                 });
                 line_processor.handle_glyph(glyph, run.line_y, color);
 
-                self.draw_glyph(pos, glyph, run.line_y);
+                self.draw_glyph(pos, glyph, run.line_y, scale);
             }
         }
+
+        // Draw underlines and strikethroughs.
+        for line in line_processor.lines() {
+            let rect = line.into_rect() + pos.to_vec2();
+            self.fill(rect, &line.color);
+        }
     }
 
     fn save(&mut self) -> Result<(), piet::Error> {
         let state = self.states.last().unwrap();
 
         self.states.push(ContextState {
-            transform: state.transform,
-            clip: state.clip.clone(),
+            clip_count: 0,
+            layer: None,
+            ..*state
         });
 
         Ok(())
@@ -266,17 +690,34 @@ where
             return Err(piet::Error::StackUnbalance);
         }
 
-        self.states.pop();
+        let mut state = self.states.pop().unwrap();
+        self.unwind(&mut state);
 
         Ok(())
     }
 
     fn finish(&mut self) -> Result<(), piet::Error> {
-        Ok(())
+        let mut states = std::mem::take(&mut self.states);
+        for state in states.iter_mut().rev() {
+            self.unwind(state);
+        }
+        self.states = states;
+
+        if self.states.len() > 1 {
+            self.set_error(piet::Error::StackUnbalance);
+        }
+
+        self.status()
     }
 
     fn transform(&mut self, transform: Affine) {
-        self.states.last_mut().unwrap().transform = transform;
+        if !transform.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let state = self.states.last_mut().unwrap();
+        state.transform *= transform;
     }
 
     fn current_transform(&self) -> Affine {
@@ -290,39 +731,44 @@ where
         buf: &[u8],
         format: piet::ImageFormat,
     ) -> Result<Self::Image, piet::Error> {
+        let expected_len = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(format.bytes_per_pixel()));
+        if expected_len != Some(buf.len()) {
+            self.set_error(piet::Error::InvalidInput);
+            return Err(piet::Error::InvalidInput);
+        }
+
         let data: Vec<u32> = match format {
             piet::ImageFormat::RgbaPremul => buf
                 .chunks_exact(4)
-                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                .map(|chunk| {
+                    let [r, g, b, a]: [u8; 4] = chunk.try_into().unwrap();
+                    u32::from_be_bytes([a, r, g, b])
+                })
                 .collect(),
             piet::ImageFormat::RgbaSeparate => buf
                 .chunks_exact(4)
                 .map(|chunk| {
-                    let [mut r, mut g, mut b, a]: [u8; 4] = chunk.try_into().unwrap();
-
-                    let premultiply = |source: u8| {
-                        (f32::from(source) * f32::from(a) / f32::from(u8::MAX)).round() as u8
-                    };
-
-                    r = premultiply(r);
-                    g = premultiply(g);
-                    b = premultiply(b);
-
-                    u32::from_le_bytes([r, g, b, a])
+                    let [r, g, b, a]: [u8; 4] = chunk.try_into().unwrap();
+                    SolidSource::from_unpremultiplied_argb(a, r, g, b).to_u32()
                 })
                 .collect(),
             piet::ImageFormat::Rgb => buf
                 .chunks_exact(3)
                 .map(|chunk| {
                     let [r, g, b]: [u8; 3] = chunk.try_into().unwrap();
-                    u32::from_le_bytes([r, g, b, 0xff])
+                    u32::from_be_bytes([0xff, r, g, b])
                 })
                 .collect(),
             piet::ImageFormat::Grayscale => buf
                 .iter()
-                .map(|v| u32::from_le_bytes([*v, *v, *v, 0xff]))
+                .map(|&v| u32::from_be_bytes([0xff, v, v, v]))
                 .collect(),
-            _ => return Err(piet::Error::NotSupported),
+            _ => {
+                self.set_error(piet::Error::NotSupported);
+                return Err(piet::Error::NotSupported);
+            }
         };
 
         Ok(RaqoteImage::new(width as i32, height as i32, data))
@@ -343,63 +789,225 @@ where
         image: &Self::Image,
         src_rect: impl Into<Rect>,
         dst_rect: impl Into<Rect>,
-        _interp: piet::InterpolationMode,
+        interp: piet::InterpolationMode,
     ) {
-        let src_image = RaqoteImage::from_region(image, src_rect);
+        let src_rect = src_rect.into();
         let dst_rect = dst_rect.into();
+        if !dst_rect.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let src_rect = src_rect.abs();
+        let visible = src_rect.intersect(image.size().to_rect());
+        if !src_rect.is_finite() || visible.is_empty() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        // Copy the pixels that the visible part of the source touches, and map the destination
+        // onto the source rect within them.
+        let region = visible.expand();
+        let src_image = match RaqoteImage::from_region(image, region) {
+            Ok(src_image) => src_image,
+            Err(err) => {
+                self.set_error(err);
+                return;
+            }
+        };
+        let scale_x = dst_rect.width() / src_rect.width();
+        let scale_y = dst_rect.height() / src_rect.height();
+        let to_src = Affine::translate(src_rect.origin() - region.origin())
+            * Affine::scale_non_uniform(scale_x.recip(), scale_y.recip())
+            * Affine::translate(-dst_rect.origin().to_vec2());
 
-        self.dt.draw_image_with_size_at(
-            dst_rect.width() as f32,
-            dst_rect.height() as f32,
-            dst_rect.x0 as f32,
-            dst_rect.y0 as f32,
-            &src_image.as_image(),
-            &DrawOptions::new(),
+        // Only draw the part of the destination that the visible part of the source maps to.
+        let dst_rect = Rect::new(
+            dst_rect.x0 + (visible.x0 - src_rect.x0) * scale_x,
+            dst_rect.y0 + (visible.y0 - src_rect.y0) * scale_y,
+            dst_rect.x1 + (visible.x1 - src_rect.x1) * scale_x,
+            dst_rect.y1 + (visible.y1 - src_rect.y1) * scale_y,
         );
+        if dst_rect.area() == 0.0 {
+            return;
+        }
+
+        // Equivalent to `DrawTarget::draw_image_with_size_at`, but with a configurable filter.
+        let source = Source::Image(
+            src_image.as_image(),
+            ExtendMode::Pad,
+            convert::to_filter_mode(interp),
+            convert::to_transform(to_src),
+        );
+        let (x, y) = (dst_rect.x0 as f32, dst_rect.y0 as f32);
+        let (width, height) = (dst_rect.width() as f32, dst_rect.height() as f32);
+
+        let options = self.draw_options();
+        self.with_transform(|dt| dt.fill_rect(x, y, width, height, &source, &options));
     }
 
     fn capture_image_area(
         &mut self,
         src_rect: impl Into<Rect>,
     ) -> Result<Self::Image, piet::Error> {
-        Ok(RaqoteImage::from_region(&*self.dt, src_rect))
+        if !self.check_no_layer() {
+            return Err(piet::Error::NotSupported);
+        }
+
+        let src_rect = self.device_transform().transform_rect_bbox(src_rect.into());
+
+        RaqoteImage::from_region(&*self.dt, src_rect).inspect_err(|_| {
+            self.set_error(piet::Error::InvalidInput);
+        })
     }
 
     fn blurred_rect(&mut self, rect: Rect, blur_radius: f64, brush: &impl IntoBrush<Self>) {
-        let size = piet::util::size_for_blurred_rect(rect, blur_radius);
-        let width = size.width as i32;
-        let height = size.height as i32;
-        if width == 0 || height == 0 {
+        if !rect.is_finite() || !blur_radius.is_finite() {
+            self.set_error(piet::Error::InvalidInput);
+            return;
+        }
+
+        let brush = brush.make_brush(self, || rect).into_owned();
+        if blur_radius <= 0.0 {
+            self.fill(rect, &brush);
             return;
         }
 
-        let mut mask = Mask {
-            width,
-            height,
-            data: vec![0; width as usize * height as usize],
+        let to_mask_space = self.blur_mask_space(blur_radius);
+        let scale = to_mask_space.determinant().abs().sqrt();
+
+        let Some((mask, mask_rect)) = blurred_rect_mask(
+            to_mask_space.transform_rect_bbox(rect),
+            blur_radius * scale,
+            self.visible_mask_rect(to_mask_space),
+        ) else {
+            return;
         };
-        let blurred_rect =
-            piet::util::compute_blurred_rect(rect, blur_radius, width as usize, &mut mask.data);
 
-        let source = brush
-            .make_brush(self, || blurred_rect)
-            .into_owned()
-            .into_source();
+        self.fill_mask(&brush, &mask, mask_rect, to_mask_space);
+    }
+}
+
+/// Rasterizes `path` without antialiasing, and returns a device-space path covering the same
+/// pixels.
+///
+/// raqote always antialiases clips, but the edges of the returned path lie on pixel boundaries, so
+/// clipping to it doesn't produce partially covered pixels. `bounds` is the device-space bounding
+/// box of the path.
+fn aliased_path(
+    path: &raqote::Path,
+    bounds: Rect,
+    transform: Affine,
+    (width, height): (i32, i32),
+) -> raqote::Path {
+    let mut builder = PathBuilder::new();
+
+    let bounds = bounds
+        .expand()
+        .intersect(Rect::new(0.0, 0.0, width.into(), height.into()));
+    if bounds.is_empty() {
+        return builder.finish();
+    }
+
+    let mut coverage = DrawTarget::new(bounds.width() as i32, bounds.height() as i32);
+    coverage.set_transform(&convert::to_transform(
+        Affine::translate(-bounds.origin().to_vec2()) * transform,
+    ));
+    coverage.fill(
+        path,
+        &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0, 0, 0)),
+        &DrawOptions {
+            antialias: AntialiasMode::None,
+            ..DrawOptions::new()
+        },
+    );
+
+    // Add a rectangle for each horizontal run of covered pixels.
+    let (x0, y0) = (bounds.x0 as f32, bounds.y0 as f32);
+    for (y, row) in coverage
+        .get_data()
+        .chunks_exact(coverage.width() as usize)
+        .enumerate()
+    {
+        let mut x = 0;
+        while x < row.len() {
+            if row[x] == 0 {
+                x += 1;
+                continue;
+            }
+
+            let start = x;
+            while x < row.len() && row[x] != 0 {
+                x += 1;
+            }
+            builder.rect(x0 + start as f32, y0 + y as f32, (x - start) as f32, 1.0);
+        }
+    }
+
+    builder.finish()
+}
+
+/// Computes the blur mask for `rect`, along with the pixel-aligned rectangle that it covers.
+///
+/// The mask is padded to fit the blur, and accounts for fractional positions within that padding.
+/// It is only accurate within `visible`, and the pixels next to it.
+fn blurred_rect_mask(rect: Rect, blur_radius: f64, visible: Rect) -> Option<(Mask, Rect)> {
+    // Crop the rect far enough outside of `visible` that the blur of the new edges can't reach it.
+    let padding = blur::BLUR_EXTENT * blur_radius;
+    let rect = rect.intersect(visible.inflate(2.0 * padding + 1.0, 2.0 * padding + 1.0));
+    if !(rect.width() > 0.0 && rect.height() > 0.0) {
+        return None;
+    }
 
-        self.dt.mask(&source, rect.x0 as i32, rect.y0 as i32, &mask);
+    // piet samples the blur at the top-left corner of each pixel. Moving the rect by half a pixel
+    // samples it at the pixel centers instead, like the other blur masks, so that the mask stays
+    // centered on the rect when the mask space is rotated.
+    let rect = rect - kurbo::Vec2::new(0.5, 0.5);
+    let size = piet::util::size_for_blurred_rect(rect, blur_radius);
+    let width = size.width as i32;
+    let height = size.height as i32;
+    if width == 0 || height == 0 {
+        return None;
     }
+
+    let mut mask = Mask {
+        width,
+        height,
+        data: vec![0; width as usize * height as usize],
+    };
+    let mask_rect =
+        piet::util::compute_blurred_rect(rect, blur_radius, width as usize, &mut mask.data);
+
+    Some((mask, mask_rect))
 }
 
 struct ContextState {
     transform: kurbo::Affine,
-    clip: Option<raqote::Path>,
+
+    /// The number of clips pushed onto the draw target while this state was active.
+    clip_count: usize,
+
+    /// If this state was created by pushing a layer, the number of its clips that were pushed
+    /// before the layer.
+    layer: Option<usize>,
+
+    blend_mode: BlendMode,
+
+    /// The opacity that drawing operations are multiplied by, between 0 and 1.
+    alpha: f32,
+
+    antialias: AntialiasMode,
 }
 
 impl Default for ContextState {
     fn default() -> Self {
         ContextState {
             transform: kurbo::Affine::IDENTITY,
-            clip: None,
+            clip_count: 0,
+            layer: None,
+            blend_mode: BlendMode::SrcOver,
+            alpha: 1.0,
+            antialias: AntialiasMode::Gray,
         }
     }
 }
@@ -2,25 +2,36 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::slice;
+use std::{
+    collections::{HashMap, VecDeque},
+    slice,
+};
 
 use piet::{
-    Color, RenderContext,
+    Color, Image, InterpolationMode, RenderContext,
     kurbo::{self, Affine, Shape},
 };
-use piet_cosmic_text::cosmic_text::{self, Command, SwashCache};
+use piet_cosmic_text::cosmic_text::{
+    self, CacheKey, Command, Placement, SwashCache, SwashContent, SwashImage,
+};
+use raqote::{SolidSource, Winding};
 
-use crate::{RaqoteRenderContext, convert};
+use crate::{RaqoteRenderContext, convert, image::RaqoteImage};
 
 impl<B> RaqoteRenderContext<'_, '_, B>
 where
     B: AsRef<[u32]> + AsMut<[u32]>,
 {
+    /// Draws a single glyph of a layout run.
+    ///
+    /// Glyphs are laid out in a copy of user space that is scaled by `scale`, so that outlines and
+    /// bitmaps are produced at the font size and subpixel position they will have on the device.
     pub(crate) fn draw_glyph(
         &mut self,
         pos: kurbo::Point,
         glyph: &cosmic_text::LayoutGlyph,
         run_y: f32,
+        scale: f64,
     ) {
         let mut glyph_cache = self
             .cache
@@ -28,51 +39,240 @@ where
             .take()
             .unwrap_or_else(SwashCache::new);
 
-        let physical = glyph.physical((0., 0.), 1.0);
+        // When the scaled space is only translated from the device, lay the glyph out at its
+        // position on the device, so that bitmaps land on whole pixels and outlines are placed in
+        // the right subpixel bins.
+        let to_device = self.device_transform() * Affine::scale(scale.recip());
+        let [a, b, c, d, e, f] = to_device.as_coeffs();
+        let is_translation = [a - 1.0, b, c, d - 1.0].iter().all(|v| v.abs() < 1e-9);
+        let device_offset = if is_translation {
+            kurbo::Vec2::new(e, f)
+        } else {
+            kurbo::Vec2::ZERO
+        };
+        let to_user = Affine::scale(scale.recip()) * Affine::translate(-device_offset);
+
+        let origin =
+            kurbo::Point::new(pos.x * scale, (pos.y + f64::from(run_y)) * scale) + device_offset;
+        let physical = glyph.physical((origin.x as f32, origin.y as f32), scale as f32);
+        let color = glyph.color_opt.map_or(piet::util::DEFAULT_TEXT_COLOR, |c| {
+            Color::rgba8(c.r(), c.g(), c.b(), c.a())
+        });
         self.cache.text.clone().with_font_system_mut(|system| {
-            if let Some(outline) = glyph_cache.get_outline_commands(system, physical.cache_key) {
-                let offset = kurbo::Affine::translate((
-                    pos.x + physical.x as f64 + physical.cache_key.x_bin.as_float() as f64,
-                    pos.y
-                        + run_y as f64
-                        + physical.y as f64
-                        + physical.cache_key.y_bin.as_float() as f64,
-                )) * Affine::scale_non_uniform(1.0, -1.0);
-                let color = glyph.color_opt.map_or(piet::util::DEFAULT_TEXT_COLOR, |c| {
-                    Color::rgba8(c.r(), c.g(), c.b(), c.a())
+            let mut glyph_paths = std::mem::take(&mut self.cache.glyph_paths);
+            let path = glyph_paths.get_or_insert_with(physical.cache_key, || {
+                let outline = glyph_cache.get_outline_commands(system, physical.cache_key)?;
+                let mut path = convert::to_path(TextShape {
+                    cmds: outline,
+                    offset: Affine::scale_non_uniform(1.0, -1.0),
                 });
+                path.winding = Winding::EvenOdd;
+                Some(path)
+            });
+
+            if let Some(path) = path {
+                let offset = kurbo::Affine::translate((
+                    physical.x as f64 + physical.cache_key.x_bin.as_float() as f64,
+                    physical.y as f64 + physical.cache_key.y_bin.as_float() as f64,
+                ));
 
-                // Fill in the outline.
-                self.fill_even_odd(
-                    TextShape {
-                        cmds: outline,
-                        offset,
-                    },
-                    &color,
+                // Fill in the outline. Glyphs are deliberately filled one at a time: raqote's
+                // rasterizer cost grows with the number of active edges per scanline, so merging
+                // the glyphs of a run into a single path is measurably slower (see the
+                // `fill_glyphs` benchmark), while the fixed cost of each fill is negligible.
+                let brush = self.solid_brush(color);
+                let source = brush.to_source();
+                let options = self.draw_options();
+                self.with_local_transform(to_user * offset, |dt| dt.fill(path, &source, &options));
+            } else if let Some(image) = glyph_cache.get_image(system, physical.cache_key) {
+                // Blit the image onto the target. The subpixel offset is already part of the
+                // rendered image.
+                let origin = kurbo::Point::new(
+                    f64::from(physical.x + image.placement.left),
+                    f64::from(physical.y - image.placement.top),
                 );
-            } else {
-                // Blit the image onto the target.
-                let default_color = {
-                    let (r, g, b, a) = piet::util::DEFAULT_TEXT_COLOR.as_rgba8();
-                    cosmic_text::Color::rgba(r, g, b, a)
-                };
-                glyph_cache.with_pixels(system, physical.cache_key, default_color, |x, y, clr| {
-                    let [r, g, b, a] = [clr.r(), clr.g(), clr.b(), clr.a()];
-                    let color = Color::rgba8(r, g, b, a);
-
-                    // Straight-blit the image.
-                    self.fill_even_odd(
-                        kurbo::Rect::from_origin_size((x as f64, y as f64), (1., 1.)),
-                        &color,
+
+                if let Some(bitmap) = glyph_image(image, color) {
+                    let dst_rect = kurbo::Rect::from_origin_size(origin, bitmap.size());
+                    self.draw_image(
+                        &bitmap,
+                        to_user.transform_rect_bbox(dst_rect),
+                        InterpolationMode::Bilinear,
                     );
-                });
+                }
             }
+
+            self.cache.glyph_paths = glyph_paths;
         });
 
         self.cache.glyph_cache = Some(glyph_cache);
     }
 }
 
+/// Returns the factor by which glyphs are scaled from their font size to reach the device.
+///
+/// Only uniform scales (optionally with rotation) are taken into account. Other transforms draw
+/// glyphs at their font size and let the rasterizer distort the outlines.
+pub(crate) fn text_scale(transform: Affine) -> f64 {
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+
+    // The columns of a similarity transform are orthogonal and have the same length.
+    let (x_len, y_len) = (a * a + b * b, c * c + d * d);
+    let tolerance = 1e-6 * x_len.max(y_len);
+    let scale = transform.determinant().abs().sqrt();
+    if (x_len - y_len).abs() <= tolerance && (a * c + b * d).abs() <= tolerance && scale.is_normal()
+    {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Hit and miss counts for the glyph path cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    /// The number of glyphs whose path was already cached.
+    pub hits: u64,
+
+    /// The number of glyphs whose path had to be converted from the outline.
+    pub misses: u64,
+}
+
+/// A least-recently-used cache of glyph outlines that have been converted to paths.
+///
+/// Paths are stored with the y-axis flipped into piet's coordinate space, relative to the glyph
+/// origin. Glyphs without an outline are cached as `None`.
+pub(crate) struct GlyphPathCache {
+    paths: HashMap<CacheKey, CachedPath>,
+
+    /// Keys in the order they were used, with the time of each use. A use is stale if the path
+    /// was used again later or was evicted, and stale uses are skipped when evicting.
+    uses: VecDeque<(CacheKey, u64)>,
+
+    limit: usize,
+    clock: u64,
+    stats: GlyphCacheStats,
+}
+
+struct CachedPath {
+    path: Option<raqote::Path>,
+    last_used: u64,
+}
+
+impl GlyphPathCache {
+    const DEFAULT_LIMIT: usize = 1024;
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        // The most recently used path is always kept, so that it can be returned.
+        self.limit = limit.max(1);
+        while self.paths.len() > self.limit {
+            self.evict();
+        }
+    }
+
+    pub(crate) fn stats(&self) -> GlyphCacheStats {
+        self.stats
+    }
+
+    pub(crate) fn get_or_insert_with(
+        &mut self,
+        key: CacheKey,
+        f: impl FnOnce() -> Option<raqote::Path>,
+    ) -> Option<&raqote::Path> {
+        self.clock += 1;
+
+        if self.paths.contains_key(&key) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+
+            if self.paths.len() >= self.limit {
+                self.evict();
+            }
+
+            self.paths.insert(
+                key,
+                CachedPath {
+                    path: f(),
+                    last_used: 0,
+                },
+            );
+        }
+
+        let entry = self.paths.get_mut(&key).unwrap();
+        entry.last_used = self.clock;
+        self.uses.push_back((key, self.clock));
+
+        // Drop stale uses once they outnumber the cached paths, so that the queue stays
+        // proportional to the cache.
+        if self.uses.len() > 2 * self.paths.len() {
+            let paths = &self.paths;
+            self.uses
+                .retain(|&(key, used)| is_latest_use(paths, key, used));
+        }
+
+        self.paths[&key].path.as_ref()
+    }
+
+    /// Removes the least recently used path.
+    fn evict(&mut self) {
+        while let Some((key, used)) = self.uses.pop_front() {
+            if is_latest_use(&self.paths, key, used) {
+                self.paths.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+/// Returns whether the use of `key` at `used` is the latest use of a path that is still cached.
+fn is_latest_use(paths: &HashMap<CacheKey, CachedPath>, key: CacheKey, used: u64) -> bool {
+    paths.get(&key).is_some_and(|entry| entry.last_used == used)
+}
+
+impl Default for GlyphPathCache {
+    fn default() -> Self {
+        GlyphPathCache {
+            paths: HashMap::new(),
+            uses: VecDeque::new(),
+            limit: Self::DEFAULT_LIMIT,
+            clock: 0,
+            stats: GlyphCacheStats::default(),
+        }
+    }
+}
+
+/// Converts a rendered glyph into an image, tinting alpha masks with `color`.
+fn glyph_image(image: &SwashImage, color: Color) -> Option<RaqoteImage> {
+    let Placement { width, height, .. } = image.placement;
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let (r, g, b, a) = color.as_rgba8();
+    let data = match image.content {
+        SwashContent::Mask => image
+            .data
+            .iter()
+            .map(|&coverage| {
+                let alpha = (u32::from(a) * u32::from(coverage) + 127) / 255;
+                SolidSource::from_unpremultiplied_argb(alpha as u8, r, g, b).to_u32()
+            })
+            .collect(),
+        SwashContent::Color => image
+            .data
+            .chunks_exact(4)
+            .map(|pixel| {
+                let [r, g, b, a]: [u8; 4] = pixel.try_into().unwrap();
+                SolidSource::from_unpremultiplied_argb(a, r, g, b).to_u32()
+            })
+            .collect(),
+        SwashContent::SubpixelMask => return None,
+    };
+
+    Some(RaqoteImage::new(width as i32, height as i32, data))
+}
+
 pub struct TextShape<'a> {
     pub cmds: &'a [Command],
     pub offset: kurbo::Affine,
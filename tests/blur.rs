@@ -0,0 +1,320 @@
+//! Tests for blurred rects and shadows.
+
+use piet::{
+    Color, RenderContext,
+    kurbo::{Affine, Circle, Point, Rect, RoundedRect, RoundedRectRadii, Shape, Vec2},
+};
+use piet_raqote::{RaqoteRenderContext, RenderContextExt};
+
+mod common;
+use common::{max_difference, render};
+
+/// Draws with `f` into a new 100 by 100 target, and returns the alpha of every pixel.
+fn alpha(f: impl FnOnce(&mut RaqoteRenderContext<'_, '_>)) -> Vec<u8> {
+    let dt = render(100, 100, f);
+    dt.get_data().iter().map(|&p| (p >> 24) as u8).collect()
+}
+
+#[test]
+fn blurred_rect_is_placed_with_subpixel_precision() {
+    let draw = |x: f64| {
+        alpha(|ctx| {
+            ctx.blurred_rect(Rect::new(x, 30.0, x + 40.0, 70.0), 4.0, &Color::BLACK);
+        })
+    };
+    let (whole, half, next) = (draw(30.0), draw(30.5), draw(31.0));
+
+    // Moving by a whole pixel moves the mask by one pixel, and moving by half a pixel places it
+    // in between.
+    let row = 50 * 100;
+    for x in 1..100 {
+        assert_eq!(next[row + x], whole[row + x - 1]);
+    }
+    for x in 20..40 {
+        assert!(whole[row + x] >= half[row + x] && half[row + x] >= next[row + x]);
+    }
+    assert_ne!(half, whole);
+    assert_ne!(half, next);
+}
+
+#[test]
+fn blurred_rect_is_centered_on_the_rect() {
+    let mask = alpha(|ctx| {
+        ctx.blurred_rect(Rect::new(30.0, 30.0, 70.0, 70.0), 4.0, &Color::BLACK);
+    });
+
+    for x in 0..50 {
+        assert_eq!(mask[50 * 100 + x], mask[50 * 100 + 99 - x]);
+        assert_eq!(mask[x * 100 + 50], mask[(99 - x) * 100 + 50]);
+    }
+}
+
+#[test]
+fn blurred_rect_follows_the_transform() {
+    let expected = alpha(|ctx| {
+        ctx.blurred_rect(Rect::new(20.0, 40.0, 80.0, 60.0), 6.0, &Color::BLACK);
+    });
+
+    let scaled = alpha(|ctx| {
+        ctx.transform(Affine::translate((10.0, 20.0)) * Affine::scale(2.0));
+        ctx.blurred_rect(Rect::new(5.0, 10.0, 35.0, 20.0), 3.0, &Color::BLACK);
+    });
+    assert!(max_difference(&expected, &scaled) <= 2);
+
+    let rotated = alpha(|ctx| {
+        ctx.transform(Affine::rotate_about(
+            std::f64::consts::FRAC_PI_2,
+            (50.0, 50.0).into(),
+        ));
+        ctx.blurred_rect(Rect::new(40.0, 20.0, 60.0, 80.0), 6.0, &Color::BLACK);
+    });
+    assert!(max_difference(&expected, &rotated) <= 3);
+}
+
+#[test]
+fn huge_blurred_rects_are_bounded() {
+    let mask = alpha(|ctx| {
+        ctx.blurred_rect(Rect::new(30.0, 30.0, 70.0, 70.0), 1e9, &Color::BLACK);
+    });
+    assert!(mask.iter().all(|&a| a == 0));
+
+    // Only the visible part of a huge rect is blurred.
+    let mask = alpha(|ctx| {
+        ctx.blurred_rect(Rect::new(-1e9, -1e9, 50.0, 1e9), 4.0, &Color::BLACK);
+    });
+    assert_eq!(mask[50 * 100], 255);
+    assert!(mask[50 * 100 + 49] > 100 && mask[50 * 100 + 50] < 155);
+    assert_eq!(mask[50 * 100 + 99], 0);
+}
+
+/// Returns the coverage of every pixel of a 100 by 100 target by `shape`, from 16 by 16 samples.
+fn coverage(shape: &impl Shape) -> Vec<f64> {
+    let mut coverage = vec![0.0; 100 * 100];
+    for (i, coverage) in coverage.iter_mut().enumerate() {
+        let (x, y) = ((i % 100) as f64, (i / 100) as f64);
+        let samples = (0..256)
+            .filter(|s| {
+                let sample = Point::new(
+                    x + (f64::from(s % 16) + 0.5) / 16.0,
+                    y + (f64::from(s / 16) + 0.5) / 16.0,
+                );
+                shape.winding(sample) != 0
+            })
+            .count();
+        *coverage = samples as f64 / 256.0;
+    }
+    coverage
+}
+
+/// Blurs the coverage of every pixel of a 100 by 100 target, returning alpha values.
+///
+/// This is a brute-force Gaussian blur with a standard deviation of `blur_radius / √2`, which
+/// extends far enough that the weights it leaves out are negligible.
+fn gaussian_blur(coverage: &[f64], blur_radius: f64) -> Vec<f64> {
+    let half = (4.0 * blur_radius).ceil() as isize;
+    let weights: Vec<f64> = (-half..=half)
+        .map(|i| (-(i * i) as f64 / (blur_radius * blur_radius)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+
+    let blur = |data: &[f64], stride: usize, step: usize| {
+        let mut blurred = vec![0.0; data.len()];
+        for (i, blurred) in blurred.iter_mut().enumerate() {
+            let position = (i / stride % 100) as isize;
+            for (offset, weight) in (-half..=half).zip(&weights) {
+                if (0..100).contains(&(position + offset)) {
+                    let j = (i as isize + offset * step as isize) as usize;
+                    *blurred += weight / total * data[j];
+                }
+            }
+        }
+        blurred
+    };
+    let horizontal = blur(coverage, 1, 1);
+    blur(&horizontal, 100, 100)
+        .into_iter()
+        .map(|v| 255.0 * v)
+        .collect()
+}
+
+/// Returns the largest difference between an alpha mask and reference alpha values.
+fn max_error(alpha: &[u8], reference: &[f64]) -> f64 {
+    (alpha.iter().zip(reference))
+        .map(|(&a, &r)| (f64::from(a) - r).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn shadows_match_a_gaussian_blur() {
+    // Compare with a blur of the filled shape rather than of its exact coverage, so that the
+    // test measures the blur and not raqote's antialiasing, which is only exact to a quarter of a
+    // pixel horizontally.
+    let circle = Circle::new((47.3, 52.6), 20.0);
+    let fill = alpha(|ctx| ctx.fill(circle, &Color::BLACK));
+    let fill: Vec<f64> = fill.iter().map(|&a| f64::from(a) / 255.0).collect();
+
+    // Wide blurs are computed at a lower resolution, and are less exact.
+    for (blur_radius, tolerance) in [(2.0, 1.0), (6.0, 1.0), (40.0, 2.0)] {
+        let shadow = alpha(|ctx| {
+            ctx.fill_shadow(circle, blur_radius, Vec2::ZERO, &Color::BLACK);
+        });
+        let error = max_error(&shadow, &gaussian_blur(&fill, blur_radius));
+        assert!(error <= tolerance, "{blur_radius}: {error}");
+    }
+}
+
+#[test]
+fn shadows_are_offset() {
+    let circle = Circle::new((40.0, 40.0), 20.0);
+    let expected = alpha(|ctx| {
+        ctx.fill_shadow(
+            circle + Vec2::new(10.0, 5.0),
+            4.0,
+            Vec2::ZERO,
+            &Color::BLACK,
+        );
+    });
+    let offset = alpha(|ctx| {
+        ctx.fill_shadow(circle, 4.0, Vec2::new(10.0, 5.0), &Color::BLACK);
+    });
+    assert_eq!(offset, expected);
+}
+
+#[test]
+fn shadows_follow_the_transform() {
+    let expected = alpha(|ctx| {
+        ctx.fill_shadow(
+            Rect::new(20.0, 40.0, 80.0, 60.0),
+            6.0,
+            Vec2::new(0.0, 4.0),
+            &Color::BLACK,
+        );
+    });
+
+    // The offset is transformed along with the shape.
+    let scaled = alpha(|ctx| {
+        ctx.transform(Affine::translate((10.0, 20.0)) * Affine::scale(2.0));
+        ctx.fill_shadow(
+            Rect::new(5.0, 10.0, 35.0, 20.0),
+            3.0,
+            Vec2::new(0.0, 2.0),
+            &Color::BLACK,
+        );
+    });
+    assert!(max_difference(&expected, &scaled) <= 2);
+
+    let rotated = alpha(|ctx| {
+        ctx.transform(Affine::rotate_about(
+            std::f64::consts::FRAC_PI_2,
+            (50.0, 50.0).into(),
+        ));
+        ctx.fill_shadow(
+            Rect::new(40.0, 20.0, 60.0, 80.0),
+            6.0,
+            Vec2::new(4.0, 0.0),
+            &Color::BLACK,
+        );
+    });
+    assert!(max_difference(&expected, &rotated) <= 3);
+}
+
+#[test]
+fn huge_shadows_are_bounded() {
+    // A huge blur spreads the shape too thin to see.
+    let mask = alpha(|ctx| {
+        ctx.fill_shadow(
+            Circle::new((50.0, 50.0), 20.0),
+            1e9,
+            Vec2::ZERO,
+            &Color::BLACK,
+        );
+    });
+    assert!(mask.iter().all(|&a| a == 0));
+
+    // Only the visible part of a huge shape is blurred.
+    let mask = alpha(|ctx| {
+        ctx.fill_shadow(
+            Rect::new(-2e4, -2e4, 50.0, 2e4),
+            4.0,
+            Vec2::ZERO,
+            &Color::BLACK,
+        );
+    });
+    assert_eq!(mask[50 * 100], 255);
+    assert!(mask[50 * 100 + 49] > 100 && mask[50 * 100 + 50] < 155);
+    assert_eq!(mask[50 * 100 + 99], 0);
+}
+
+#[test]
+fn blurred_rounded_rects_match_a_gaussian_blur() {
+    // Rounded rect masks are computed without rasterizing, so compare them with the blur of the
+    // exact coverage of the shape. Narrower blurs are less exact, since the corners are split into
+    // slabs that are a fixed height.
+    let rect = Rect::new(20.3, 25.2, 80.0, 75.7);
+    for (radii, blur_radius, tolerance) in [
+        (RoundedRectRadii::from_single_radius(12.0), 6.0, 1.5),
+        (RoundedRectRadii::new(0.0, 20.0, 5.0, 30.0), 3.0, 2.5),
+        (RoundedRectRadii::from_single_radius(25.0), 2.0, 5.0),
+    ] {
+        let mask = alpha(|ctx| {
+            ctx.blurred_rounded_rect(rect, radii, blur_radius, &Color::BLACK);
+        });
+        let reference = gaussian_blur(&coverage(&RoundedRect::from_rect(rect, radii)), blur_radius);
+        let error = max_error(&mask, &reference);
+        assert!(error <= tolerance, "{blur_radius}: {error}");
+    }
+}
+
+#[test]
+fn blurred_rounded_rects_follow_the_transform() {
+    let expected = alpha(|ctx| {
+        ctx.blurred_rounded_rect(
+            Rect::new(20.0, 30.0, 80.0, 70.0),
+            RoundedRectRadii::new(0.0, 10.0, 20.0, 0.0),
+            4.0,
+            &Color::BLACK,
+        );
+    });
+
+    // Flipping the rect around also flips its corners.
+    let flipped = alpha(|ctx| {
+        ctx.transform(Affine::new([-2.0, 0.0, 0.0, 2.0, 100.0, 0.0]));
+        ctx.blurred_rounded_rect(
+            Rect::new(10.0, 15.0, 40.0, 35.0),
+            RoundedRectRadii::new(5.0, 0.0, 0.0, 10.0),
+            2.0,
+            &Color::BLACK,
+        );
+    });
+    assert!(max_difference(&expected, &flipped) <= 2);
+
+    let rotated = alpha(|ctx| {
+        ctx.transform(Affine::rotate_about(
+            std::f64::consts::FRAC_PI_2,
+            (50.0, 50.0).into(),
+        ));
+        ctx.blurred_rounded_rect(
+            Rect::new(30.0, 20.0, 70.0, 80.0),
+            RoundedRectRadii::new(10.0, 20.0, 0.0, 0.0),
+            4.0,
+            &Color::BLACK,
+        );
+    });
+    assert!(max_difference(&expected, &rotated) <= 3);
+}
+
+#[test]
+fn huge_blurred_rounded_rects_are_bounded() {
+    let mask = alpha(|ctx| {
+        ctx.blurred_rounded_rect(Rect::new(30.0, 30.0, 70.0, 70.0), 10.0, 1e9, &Color::BLACK);
+    });
+    assert!(mask.iter().all(|&a| a == 0));
+
+    // Only the visible part of huge rects and corners is computed.
+    let mask = alpha(|ctx| {
+        ctx.blurred_rounded_rect(Rect::new(-1e9, -1e9, 50.0, 1e9), 1e8, 4.0, &Color::BLACK);
+    });
+    assert_eq!(mask[50 * 100], 255);
+    assert!(mask[50 * 100 + 49] > 100 && mask[50 * 100 + 50] < 155);
+    assert_eq!(mask[50 * 100 + 99], 0);
+}
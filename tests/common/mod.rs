@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate only uses some of the helpers.
+#![allow(dead_code)]
+
+use piet::RenderContext;
+use piet_raqote::{Cache, RaqoteRenderContext};
+use raqote::DrawTarget;
+
+/// Draws with `f` into a new `width` by `height` target, and returns the target.
+pub fn render(
+    width: i32,
+    height: i32,
+    f: impl FnOnce(&mut RaqoteRenderContext<'_, '_>),
+) -> DrawTarget {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(width, height);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+    f(&mut ctx);
+    ctx.finish().unwrap();
+    drop(ctx);
+    dt
+}
+
+/// Returns the largest difference between two sets of channels.
+pub fn max_difference(a: &[u8], b: &[u8]) -> u8 {
+    a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap()
+}
@@ -0,0 +1,189 @@
+//! Tests for filter effects.
+
+use piet::{
+    Color, RenderContext,
+    kurbo::{Rect, Size},
+};
+use piet_raqote::{Cache, Filter, RaqoteRenderContext, RenderContextExt};
+use raqote::{BlendMode, DrawTarget};
+
+mod common;
+use common::render;
+
+const RED_COLOR: Color = Color::rgb8(255, 0, 0);
+const RED: u32 = 0xffff0000;
+/// Blue at half opacity, premultiplied.
+const TRANSLUCENT_BLUE: u32 = 0x80000080;
+
+/// Draws a target of the given size that is red up to `split` and translucent blue after it, then
+/// calls `f`.
+///
+/// Returns the pixels of the target.
+fn draw(size: Size, split: f64, f: impl FnOnce(&mut RaqoteRenderContext<'_, '_>)) -> Vec<u32> {
+    let dt = render(size.width as i32, size.height as i32, |ctx| {
+        ctx.fill(Rect::new(0.0, 0.0, split, size.height), &RED_COLOR);
+        ctx.fill(
+            Rect::new(split, 0.0, size.width, size.height),
+            &Color::rgba8(0, 0, 255, 128),
+        );
+        f(ctx);
+    });
+    dt.get_data().to_vec()
+}
+
+/// Draws a 20 by 20 target that is red on the left and translucent blue on the right, then applies
+/// `filter` within `rect`.
+fn filtered(rect: Rect, filter: &Filter) -> Vec<u32> {
+    draw(Size::new(20.0, 20.0), 10.0, |ctx| {
+        ctx.apply_filter(rect, filter)
+    })
+}
+
+/// Returns the largest difference between the channels of two pixels.
+fn channel_difference(a: u32, b: u32) -> u8 {
+    (a.to_be_bytes().iter())
+        .zip(b.to_be_bytes())
+        .map(|(a, b)| a.abs_diff(b))
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn color_matrices_apply_within_the_rect() {
+    let data = filtered(Rect::new(0.0, 0.0, 5.0, 20.0), &Filter::grayscale());
+    let gray = (0.2126f32 * 255.0).round() as u32;
+    assert_eq!(data[0], 0xff000000 | gray << 16 | gray << 8 | gray);
+    assert_eq!(data[5], RED);
+
+    // Translucent colors are unpremultiplied before they are transformed.
+    let data = filtered(Rect::new(10.0, 0.0, 20.0, 20.0), &Filter::saturate(1.0));
+    assert!(data[10..20].iter().all(|&p| p == TRANSLUCENT_BLUE));
+}
+
+#[test]
+fn desaturating_fully_is_grayscale() {
+    assert_eq!(Filter::saturate(0.0), Filter::grayscale());
+    assert_eq!(
+        filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::saturate(0.0)),
+        filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::grayscale())
+    );
+}
+
+#[test]
+fn gaussian_blurs_apply_within_the_rect() {
+    let data = filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::GaussianBlur(2.0));
+
+    // The edges of the target are repeated, so the far left stays red, and every column is
+    // still uniform.
+    assert_eq!(data[10 * 20], RED);
+    for y in 0..20 {
+        assert_eq!(data[y * 20 + 9], data[10 * 20 + 9]);
+    }
+    assert_ne!(data[10 * 20 + 9], RED);
+
+    // Pixels outside of the rect are read, but not modified.
+    let data = filtered(Rect::new(8.0, 5.0, 12.0, 6.0), &Filter::GaussianBlur(2.0));
+    assert_eq!(data[4 * 20 + 9], RED);
+    assert_ne!(data[5 * 20 + 9], RED);
+    assert_eq!(data[5 * 20 + 7], RED);
+}
+
+#[test]
+fn wide_gaussian_blurs_repeat_the_edges() {
+    // A blur that reaches past the edges of the target matches the blur of a wider target that
+    // repeats them.
+    let data = filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::GaussianBlur(10.0));
+    let wider = draw(Size::new(60.0, 20.0), 30.0, |ctx| {
+        ctx.apply_filter(
+            Rect::new(20.0, 0.0, 40.0, 20.0),
+            &Filter::GaussianBlur(10.0),
+        );
+    });
+    for x in 0..20 {
+        let difference = channel_difference(data[10 * 20 + x], wider[10 * 60 + 20 + x]);
+        assert!(difference <= 1, "{x}: {difference}");
+    }
+
+    // A huge blur averages the edges.
+    let data = filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::GaussianBlur(1e9));
+    for &pixel in &data {
+        assert!(channel_difference(pixel, 0xc0800040) <= 1, "{pixel:08x}");
+    }
+}
+
+#[test]
+fn convolutions_weigh_neighboring_pixels() {
+    let unfiltered = filtered(Rect::ZERO, &Filter::grayscale());
+
+    let identity = Filter::Convolve {
+        width: 3,
+        height: 3,
+        kernel: vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+    };
+    assert_eq!(
+        filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &identity),
+        unfiltered
+    );
+
+    // The kernel is rotated, so each pixel takes the value of the one to its left, and the edge
+    // is repeated.
+    let shift = Filter::Convolve {
+        width: 3,
+        height: 1,
+        kernel: vec![0.0, 0.0, 1.0],
+    };
+    let data = filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &shift);
+    assert_eq!(data[10], unfiltered[9]);
+    assert_eq!(data[0], unfiltered[0]);
+
+    // The top-left weight applies to the pixel below and to the right.
+    let shift = Filter::Convolve {
+        width: 3,
+        height: 3,
+        kernel: vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    };
+    let data = filtered(Rect::new(0.0, 0.0, 20.0, 20.0), &shift);
+    assert_eq!(data[5 * 20 + 9], unfiltered[6 * 20 + 10]);
+    assert_eq!(data[19 * 20 + 19], unfiltered[19 * 20 + 19]);
+}
+
+#[test]
+fn invalid_filters_are_rejected() {
+    let invalid = [
+        Filter::GaussianBlur(f64::NAN),
+        Filter::ColorMatrix([f32::INFINITY; 20]),
+        Filter::Convolve {
+            width: 2,
+            height: 1,
+            kernel: vec![1.0, 1.0],
+        },
+        Filter::Convolve {
+            width: 3,
+            height: 3,
+            kernel: vec![1.0],
+        },
+    ];
+    for filter in invalid {
+        let mut cache = Cache::new();
+        let mut dt = DrawTarget::new(20, 20);
+        let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+        ctx.apply_filter(Rect::new(0.0, 0.0, 5.0, 5.0), &filter);
+        assert!(
+            matches!(ctx.finish(), Err(piet::Error::InvalidInput)),
+            "{filter:?}"
+        );
+    }
+}
+
+#[test]
+fn filters_are_not_supported_in_layers() {
+    let dt = render(20, 20, |ctx| {
+        ctx.fill(Rect::new(0.0, 0.0, 20.0, 20.0), &RED_COLOR);
+        ctx.push_layer(1.0, BlendMode::SrcOver, None::<Rect>);
+        ctx.apply_filter(Rect::new(0.0, 0.0, 20.0, 20.0), &Filter::grayscale());
+        assert!(matches!(ctx.status(), Err(piet::Error::NotSupported)));
+        ctx.pop_layer().unwrap();
+    });
+
+    assert!(dt.get_data().iter().all(|&p| p == RED));
+}
@@ -0,0 +1,349 @@
+//! Tests for gradient brushes.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use piet::{
+    Color, FixedGradient, FixedLinearGradient, FixedRadialGradient, GradientStop, RenderContext,
+    kurbo::{Affine, Point, Rect, Vec2},
+};
+use piet_raqote::{Brush, RaqoteRenderContext};
+use raqote::Spread;
+
+mod common;
+use common::{max_difference, render};
+
+/// Stops from black to white.
+fn black_to_white() -> Vec<GradientStop> {
+    vec![
+        GradientStop {
+            pos: 0.0,
+            color: Color::BLACK,
+        },
+        GradientStop {
+            pos: 1.0,
+            color: Color::WHITE,
+        },
+    ]
+}
+
+/// Fills a new `width` by `height` target with the brush that `make_brush` creates.
+///
+/// `make_brush` may also set a transform, and the whole target is filled through it. Returns the
+/// blue channel of every pixel, which is where a gray gradient is between black and white.
+fn fill(
+    width: i32,
+    height: i32,
+    make_brush: impl FnOnce(&mut RaqoteRenderContext<'_, '_>) -> Brush,
+) -> Vec<u8> {
+    let dt = render(width, height, |ctx| {
+        let brush = make_brush(ctx);
+        let target = Rect::new(0.0, 0.0, width.into(), height.into());
+        let to_user = ctx.current_transform().inverse();
+        ctx.fill(to_user.transform_rect_bbox(target), &brush);
+    });
+    dt.get_data().iter().map(|&p| p as u8).collect()
+}
+
+#[test]
+fn radial_gradient_origin_offset_moves_the_focal_point() {
+    let data = fill(100, 100, |ctx| {
+        ctx.gradient(FixedGradient::Radial(FixedRadialGradient {
+            center: (50.0, 50.0).into(),
+            origin_offset: Vec2::new(-20.0, 0.0),
+            radius: 40.0,
+            stops: black_to_white(),
+        }))
+        .unwrap()
+    });
+    let at = |x: usize, y: usize| i32::from(data[y * 100 + x]);
+
+    // The gradient starts at the focal point, and reaches the circle sooner on the side the
+    // focal point was moved to.
+    assert!(at(30, 50) < 10);
+    assert!((at(20, 50) - 128).abs() < 12, "{}", at(20, 50));
+    assert!((at(40, 50) - 43).abs() < 12, "{}", at(40, 50));
+    assert_eq!(at(30, 39), at(30, 60));
+}
+
+#[test]
+fn radial_gradient_without_offset_is_centered() {
+    let data = fill(100, 100, |ctx| {
+        ctx.gradient(FixedGradient::Radial(FixedRadialGradient {
+            center: (50.0, 50.0).into(),
+            origin_offset: Vec2::ZERO,
+            radius: 40.0,
+            stops: black_to_white(),
+        }))
+        .unwrap()
+    });
+    let at = |x: usize, y: usize| data[y * 100 + x];
+
+    assert!(at(50, 50) < 5);
+    assert_eq!(at(29, 50), at(70, 50));
+    assert_eq!(at(5, 50), 255);
+}
+
+/// Fills a 40 by 1 target with a linear gradient from black to white over the first 10 pixels.
+fn linear_with_spread(spread: Spread) -> Vec<u8> {
+    fill(40, 1, |_| {
+        Brush::linear_gradient_with_spread(
+            FixedLinearGradient {
+                start: (0.0, 0.0).into(),
+                end: (10.0, 0.0).into(),
+                stops: black_to_white(),
+            },
+            spread,
+        )
+        .unwrap()
+    })
+}
+
+#[test]
+fn linear_gradients_are_spread() {
+    let pad = linear_with_spread(Spread::Pad);
+    assert!(pad[..10].is_sorted());
+    assert!(pad[10..].iter().all(|&v| v == 255));
+
+    let repeat = linear_with_spread(Spread::Repeat);
+    assert_eq!(repeat[..10], pad[..10]);
+    for x in 0..30 {
+        assert_eq!(repeat[x + 10], repeat[x]);
+    }
+
+    // Reflected gradients go back from white to black, and then repeat.
+    let reflect = linear_with_spread(Spread::Reflect);
+    assert_eq!(reflect[..10], pad[..10]);
+    for x in 0..10 {
+        assert!(reflect[10 + x].abs_diff(reflect[9 - x]) <= 1);
+        assert_eq!(reflect[20 + x], reflect[x]);
+    }
+}
+
+#[test]
+fn radial_gradients_are_spread() {
+    let data = fill(100, 1, |_| {
+        Brush::radial_gradient_with_spread(
+            FixedRadialGradient {
+                center: (0.0, 0.0).into(),
+                origin_offset: Vec2::ZERO,
+                radius: 20.0,
+                stops: black_to_white(),
+            },
+            Spread::Repeat,
+        )
+        .unwrap()
+    });
+
+    // Pixels next to the center are further from it than their x coordinate, so compare the
+    // rings after the first one.
+    assert!(data[..20].is_sorted());
+    for x in 20..80 {
+        assert_eq!(data[x + 20], data[x]);
+    }
+}
+
+#[test]
+fn gradients_need_finite_inputs() {
+    let linear = |start: (f64, f64), pos: f32| {
+        Brush::linear_gradient_with_spread(
+            FixedLinearGradient {
+                start: start.into(),
+                end: (10.0, 0.0).into(),
+                stops: vec![GradientStop {
+                    pos,
+                    color: Color::BLACK,
+                }],
+            },
+            Spread::Pad,
+        )
+    };
+    assert!(linear((0.0, 0.0), 0.0).is_ok());
+    assert!(matches!(
+        linear((f64::NAN, 0.0), 0.0),
+        Err(piet::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        linear((0.0, 0.0), f32::INFINITY),
+        Err(piet::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        Brush::linear_gradient_with_spread(
+            FixedLinearGradient {
+                start: (0.0, 0.0).into(),
+                end: (10.0, 0.0).into(),
+                stops: vec![],
+            },
+            Spread::Pad,
+        ),
+        Err(piet::Error::InvalidInput)
+    ));
+
+    let radial = |origin_offset: Vec2, radius: f64| {
+        Brush::radial_gradient_with_spread(
+            FixedRadialGradient {
+                center: (0.0, 0.0).into(),
+                origin_offset,
+                radius,
+                stops: black_to_white(),
+            },
+            Spread::Pad,
+        )
+    };
+    assert!(radial(Vec2::ZERO, 10.0).is_ok());
+    assert!(matches!(
+        radial(Vec2::new(f64::INFINITY, 0.0), 10.0),
+        Err(piet::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        radial(Vec2::ZERO, f64::NAN),
+        Err(piet::Error::InvalidInput)
+    ));
+}
+
+/// Fills a 100 by 100 target through `transform` with a sweep gradient from black to white
+/// around the center of the target.
+///
+/// Returns a function that samples the target at a device-space angle, 30 pixels from the center.
+fn sweep(
+    start_angle: f64,
+    end_angle: f64,
+    spread: Spread,
+    transform: Affine,
+) -> impl Fn(f64) -> i32 {
+    let data = fill(100, 100, |ctx| {
+        ctx.transform(transform);
+        Brush::sweep_gradient(
+            transform.inverse() * Point::new(50.0, 50.0),
+            start_angle,
+            end_angle,
+            [Color::BLACK, Color::WHITE].as_slice(),
+            spread,
+        )
+        .unwrap()
+    });
+    move |angle: f64| {
+        let p = Point::new(50.0, 50.0) + 30.0 * Vec2::from_angle(angle);
+        i32::from(data[p.y as usize * 100 + p.x as usize])
+    }
+}
+
+#[test]
+fn sweep_gradients_follow_the_angle() {
+    let at = sweep(0.0, 2.0 * PI, Spread::Pad, Affine::IDENTITY);
+    for (angle, expected) in [
+        (0.1, 4),
+        (FRAC_PI_2, 64),
+        (PI, 128),
+        (1.5 * PI, 191),
+        (6.1, 248),
+    ] {
+        assert!((at(angle) - expected).abs() <= 4, "{angle}: {}", at(angle));
+    }
+}
+
+#[test]
+fn sweep_gradients_start_and_end_at_the_angles() {
+    // The end color pads the rest of the circle, up to the start angle.
+    let at = sweep(FRAC_PI_2, PI, Spread::Pad, Affine::IDENTITY);
+    assert!(at(FRAC_PI_2 + 0.1) < 20, "{}", at(FRAC_PI_2 + 0.1));
+    assert!((at(0.75 * PI) - 128).abs() <= 4, "{}", at(0.75 * PI));
+    assert_eq!(at(1.5 * PI), 255);
+    assert_eq!(at(0.3), 255);
+
+    // Gradients can go backwards, and be repeated.
+    let at = sweep(0.0, -FRAC_PI_2, Spread::Repeat, Affine::IDENTITY);
+    assert!((at(-FRAC_PI_4) - 128).abs() <= 4, "{}", at(-FRAC_PI_4));
+    assert!((at(-0.75 * PI) - 128).abs() <= 4, "{}", at(-0.75 * PI));
+    assert!(at(-0.1) < at(-0.4));
+}
+
+#[test]
+fn sweep_gradients_need_finite_inputs() {
+    let sweep = |center: (f64, f64), end_angle: f64, pos: f32| {
+        let stops = [GradientStop {
+            pos,
+            color: Color::BLACK,
+        }];
+        Brush::sweep_gradient(center, 0.0, end_angle, stops.as_slice(), Spread::Pad)
+    };
+    assert!(sweep((0.0, 0.0), PI, 0.0).is_ok());
+    for brush in [
+        sweep((f64::NAN, 0.0), PI, 0.0),
+        sweep((0.0, 0.0), f64::INFINITY, 0.0),
+        sweep((0.0, 0.0), PI, f32::NAN),
+    ] {
+        assert!(matches!(brush, Err(piet::Error::InvalidInput)));
+    }
+    assert!(matches!(
+        Brush::sweep_gradient((0.0, 0.0), 0.0, PI, Vec::<GradientStop>::new(), Spread::Pad),
+        Err(piet::Error::InvalidInput)
+    ));
+}
+
+#[test]
+fn sweep_gradients_follow_the_transform() {
+    let at = sweep(
+        0.0,
+        PI,
+        Spread::Pad,
+        Affine::rotate_about(FRAC_PI_2, (50.0, 50.0).into()),
+    );
+
+    // The gradient is rotated by a quarter turn along with everything else.
+    assert!(at(FRAC_PI_2 + 0.1) < 10, "{}", at(FRAC_PI_2 + 0.1));
+    assert!((at(PI) - 128).abs() <= 4, "{}", at(PI));
+    assert_eq!(at(0.0), 255);
+}
+
+/// Fills a 100 by 100 target through `transform` with `gradient`.
+fn fill_gradient(transform: Affine, gradient: FixedGradient) -> Vec<u8> {
+    fill(100, 100, |ctx| {
+        ctx.transform(transform);
+        ctx.gradient(gradient).unwrap()
+    })
+}
+
+#[test]
+fn linear_gradients_follow_the_transform() {
+    let linear = |start: (f64, f64), end: (f64, f64)| {
+        FixedGradient::Linear(FixedLinearGradient {
+            start: start.into(),
+            end: end.into(),
+            stops: black_to_white(),
+        })
+    };
+    let transform =
+        Affine::translate((20.0, 20.0)) * Affine::rotate(FRAC_PI_2) * Affine::scale(2.0);
+
+    // The gradient goes down from (20, 20) to (20, 40) on the target.
+    let transformed = fill_gradient(transform, linear((0.0, 0.0), (10.0, 0.0)));
+    let expected = fill_gradient(Affine::IDENTITY, linear((20.0, 20.0), (20.0, 40.0)));
+    assert!(max_difference(&transformed, &expected) <= 1);
+    assert_eq!(transformed[10 * 100 + 50], 0);
+    assert_eq!(transformed[50 * 100 + 50], 255);
+}
+
+#[test]
+fn radial_gradients_follow_the_transform() {
+    let radial = |center: (f64, f64), origin_offset: Vec2, radius: f64| {
+        FixedGradient::Radial(FixedRadialGradient {
+            center: center.into(),
+            origin_offset,
+            radius,
+            stops: black_to_white(),
+        })
+    };
+    let transform = Affine::translate((50.0, 50.0)) * Affine::scale(2.0);
+
+    let transformed = fill_gradient(transform, radial((0.0, 0.0), Vec2::ZERO, 15.0));
+    let expected = fill_gradient(Affine::IDENTITY, radial((50.0, 50.0), Vec2::ZERO, 30.0));
+    assert!(max_difference(&transformed, &expected) <= 1);
+
+    // Gradients with a moved focal point are transformed too.
+    let transformed = fill_gradient(transform, radial((0.0, 0.0), Vec2::new(-5.0, 0.0), 15.0));
+    let expected = fill_gradient(
+        Affine::IDENTITY,
+        radial((50.0, 50.0), Vec2::new(-10.0, 0.0), 30.0),
+    );
+    assert!(max_difference(&transformed, &expected) <= 1);
+}
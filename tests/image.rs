@@ -0,0 +1,273 @@
+//! Tests for making, drawing and capturing images.
+
+use piet::{
+    Color, Image, ImageFormat, InterpolationMode, RenderContext,
+    kurbo::{Affine, Rect, Size},
+};
+use piet_raqote::{Brush, Cache, ImageExtend, RaqoteRenderContext};
+use raqote::DrawTarget;
+
+mod common;
+use common::render;
+
+const RED: u32 = 0xffff0000;
+const BLUE: u32 = 0xff0000ff;
+
+/// Makes an image from `buf`, draws it, captures it and draws the capture onto a second target.
+///
+/// Returns the pixels of the second target, after checking that they match the first.
+fn round_trip(width: usize, height: usize, buf: &[u8], format: ImageFormat) -> Vec<u32> {
+    let rect = Rect::new(0.0, 0.0, width as f64, height as f64);
+
+    let mut captured = None;
+    let dt = render(width as i32, height as i32, |ctx| {
+        let image = ctx.make_image(width, height, buf, format).unwrap();
+        ctx.draw_image(&image, rect, InterpolationMode::NearestNeighbor);
+        captured = Some(ctx.capture_image_area(rect).unwrap());
+    });
+
+    let copy = render(width as i32, height as i32, |ctx| {
+        let captured = captured.unwrap();
+        ctx.draw_image(&captured, rect, InterpolationMode::NearestNeighbor);
+    });
+
+    assert_eq!(dt.get_data(), copy.get_data());
+    copy.get_data().to_vec()
+}
+
+#[test]
+fn round_trip_rgba_premul() {
+    let data = round_trip(
+        2,
+        1,
+        &[255, 0, 0, 255, 0, 0, 128, 128],
+        ImageFormat::RgbaPremul,
+    );
+    assert_eq!(data, [0xffff0000, 0x80000080]);
+}
+
+#[test]
+fn round_trip_rgba_separate() {
+    let data = round_trip(
+        2,
+        1,
+        &[255, 0, 0, 255, 0, 0, 255, 128],
+        ImageFormat::RgbaSeparate,
+    );
+    assert_eq!(data, [0xffff0000, 0x80000080]);
+}
+
+#[test]
+fn round_trip_rgb() {
+    let data = round_trip(2, 1, &[255, 0, 0, 0, 0, 255], ImageFormat::Rgb);
+    assert_eq!(data, [0xffff0000, 0xff0000ff]);
+}
+
+#[test]
+fn round_trip_grayscale() {
+    let data = round_trip(2, 1, &[128, 255], ImageFormat::Grayscale);
+    assert_eq!(data, [0xff808080, 0xffffffff]);
+}
+
+/// Draws a red and a blue pixel scaled up to the 8 by 1 target, through a scaling transform.
+fn draw_scaled(interp: InterpolationMode) -> Vec<u32> {
+    let dt = render(8, 1, |ctx| {
+        let image = ctx
+            .make_image(2, 1, &[255, 0, 0, 0, 0, 255], ImageFormat::Rgb)
+            .unwrap();
+        ctx.transform(Affine::scale(2.0));
+        ctx.draw_image(&image, Rect::new(0.0, 0.0, 4.0, 0.5), interp);
+    });
+    dt.get_data().to_vec()
+}
+
+#[test]
+fn nearest_neighbor_is_not_filtered() {
+    let data = draw_scaled(InterpolationMode::NearestNeighbor);
+    assert_eq!(data[..4], [0xffff0000; 4]);
+    assert_eq!(data[4..], [0xff0000ff; 4]);
+}
+
+#[test]
+fn bilinear_is_filtered() {
+    let data = draw_scaled(InterpolationMode::Bilinear);
+    assert_eq!(data[0], 0xffff0000);
+    assert_eq!(data[7], 0xff0000ff);
+    assert!(
+        data[3..5]
+            .iter()
+            .all(|&p| p != 0xffff0000 && p != 0xff0000ff)
+    );
+}
+
+#[test]
+fn capture_is_clamped_to_the_target() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(10, 10);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+
+    let captured = ctx
+        .capture_image_area(Rect::new(-5.0, -5.0, 2.5, 3.2))
+        .unwrap();
+    assert_eq!(captured.size(), Size::new(3.0, 3.0));
+
+    assert!(
+        ctx.capture_image_area(Rect::new(20.0, 20.0, 30.0, 30.0))
+            .is_err()
+    );
+    assert!(
+        ctx.capture_image_area(Rect::new(0.0, 0.0, f64::NAN, 1.0))
+            .is_err()
+    );
+
+    // Capture rects are in user space.
+    ctx.transform(Affine::translate((5.0, 5.0)));
+    let captured = ctx
+        .capture_image_area(Rect::new(0.0, 0.0, 10.0, 10.0))
+        .unwrap();
+    assert_eq!(captured.size(), Size::new(5.0, 5.0));
+
+    assert!(ctx.finish().is_err());
+}
+
+#[test]
+fn draw_image_area_is_clamped_to_the_source() {
+    let dt = render(10, 10, |ctx| {
+        ctx.clear(None, Color::rgb8(255, 0, 0));
+        let image = ctx
+            .make_image(1, 1, &[0, 0, 255], ImageFormat::Rgb)
+            .unwrap();
+
+        // Only the part of the destination that the source overlaps is drawn.
+        ctx.draw_image_area(
+            &image,
+            Rect::new(-1.0, 0.0, 1.0, 1.0),
+            Rect::new(0.0, 0.0, 4.0, 2.0),
+            InterpolationMode::NearestNeighbor,
+        );
+        assert!(ctx.status().is_ok());
+
+        ctx.draw_image_area(
+            &image,
+            Rect::new(5.0, 5.0, 6.0, 6.0),
+            Rect::new(0.0, 0.0, 10.0, 10.0),
+            InterpolationMode::NearestNeighbor,
+        );
+        assert!(ctx.status().is_err());
+    });
+
+    let data = dt.get_data();
+    assert_eq!(data[..4], [0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]);
+    assert_eq!(data[10..14], data[..4]);
+    assert_eq!(data[20..24], [0xffff0000; 4]);
+}
+
+#[test]
+fn draw_image_area_maps_fractional_sources_onto_the_destination() {
+    let dt = render(30, 1, |ctx| {
+        let image = ctx
+            .make_image(3, 1, &[255, 0, 0, 0, 255, 0, 0, 0, 255], ImageFormat::Rgb)
+            .unwrap();
+
+        // Each pixel of the source is 10 pixels wide in the destination, and the first one is cut
+        // in half.
+        ctx.draw_image_area(
+            &image,
+            Rect::new(0.5, 0.0, 2.5, 1.0),
+            Rect::new(0.0, 0.0, 20.0, 1.0),
+            InterpolationMode::NearestNeighbor,
+        );
+    });
+
+    let data = dt.get_data();
+    assert_eq!(data[..5], [0xffff0000; 5]);
+    assert_eq!(data[5..15], [0xff00ff00; 10]);
+    assert_eq!(data[15..20], [0xff0000ff; 5]);
+    assert_eq!(data[20..], [0; 10]);
+}
+
+/// Fills a 10 by 2 target with a brush of a red and a blue pixel, scaled by 2 and extended with
+/// `extend`.
+///
+/// Returns the pixels of the target.
+fn fill_with_image_brush(extend: ImageExtend, transform: Affine) -> Vec<u32> {
+    let dt = render(10, 2, |ctx| {
+        let image = ctx
+            .make_image(2, 1, &[255, 0, 0, 0, 0, 255], ImageFormat::Rgb)
+            .unwrap();
+        let brush = Brush::image(
+            &image,
+            Affine::scale(2.0),
+            extend,
+            InterpolationMode::NearestNeighbor,
+        )
+        .unwrap();
+        ctx.transform(transform);
+        ctx.fill(Rect::new(-10.0, 0.0, 10.0, 2.0), &brush);
+    });
+    dt.get_data().to_vec()
+}
+
+#[test]
+fn image_brushes_are_extended() {
+    for (extend, expected) in [
+        (ImageExtend::Pad, [RED, BLUE, BLUE, BLUE, BLUE]),
+        (ImageExtend::Repeat, [RED, BLUE, RED, BLUE, RED]),
+        (ImageExtend::Reflect, [RED, BLUE, BLUE, RED, RED]),
+        (ImageExtend::None, [RED, BLUE, 0, 0, 0]),
+    ] {
+        // Sample the middle of each 2 by 2 block that a pixel of the image covers.
+        let data = fill_with_image_brush(extend, Affine::IDENTITY);
+        let blocks: Vec<_> = (0..5).map(|i| data[2 * i + 1]).collect();
+        assert_eq!(blocks, expected, "{extend:?}");
+        assert_eq!(data[10..], data[..10], "{extend:?}");
+    }
+}
+
+#[test]
+fn image_brushes_follow_the_transform() {
+    let data = fill_with_image_brush(ImageExtend::None, Affine::translate((4.0, 0.0)));
+    assert_eq!(data[..10], [0, 0, 0, 0, RED, RED, BLUE, BLUE, 0, 0]);
+}
+
+#[test]
+fn image_brushes_need_an_invertible_transform() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(1, 1);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+    let image = ctx
+        .make_image(1, 1, &[255, 0, 0], ImageFormat::Rgb)
+        .unwrap();
+    let brush = Brush::image(
+        &image,
+        Affine::scale(0.0),
+        ImageExtend::Pad,
+        InterpolationMode::Bilinear,
+    );
+    assert!(matches!(brush, Err(piet::Error::InvalidInput)));
+}
+
+#[test]
+fn image_brushes_need_a_non_empty_image() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(1, 1);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+    let image = ctx.make_image(0, 0, &[], ImageFormat::Rgb).unwrap();
+    for extend in [
+        ImageExtend::Pad,
+        ImageExtend::Repeat,
+        ImageExtend::Reflect,
+        ImageExtend::None,
+    ] {
+        let brush = Brush::image(
+            &image,
+            Affine::IDENTITY,
+            extend,
+            InterpolationMode::Bilinear,
+        );
+        assert!(
+            matches!(brush, Err(piet::Error::InvalidInput)),
+            "{extend:?}"
+        );
+    }
+}
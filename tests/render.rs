@@ -0,0 +1,557 @@
+//! Tests that draw with the context state and check the rendered pixels.
+
+use piet::{
+    Color, FontFamily, ImageFormat, InterpolationMode, RenderContext, Text, TextLayoutBuilder,
+    kurbo::{Affine, Circle, Line, Rect},
+};
+use piet_raqote::{Cache, RaqoteRenderContext, RenderContextExt};
+use raqote::{AntialiasMode, BlendMode, DrawOptions, DrawTarget, SolidSource, Source, Transform};
+
+mod common;
+use common::render;
+
+const RED: u32 = 0xffff0000;
+
+fn pixel(dt: &DrawTarget, x: i32, y: i32) -> u32 {
+    dt.get_data()[(y * dt.width() + x) as usize]
+}
+
+#[test]
+fn transform_moves_fills() {
+    let dt = render(20, 20, |ctx| {
+        ctx.transform(Affine::translate((10.0, 10.0)));
+        ctx.fill(Rect::new(0.0, 0.0, 5.0, 5.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_eq!(pixel(&dt, 2, 2), 0);
+    assert_eq!(pixel(&dt, 12, 12), RED);
+    assert_eq!(pixel(&dt, 16, 16), 0);
+}
+
+#[test]
+fn transforms_are_premultiplied() {
+    let dt = render(20, 20, |ctx| {
+        ctx.transform(Affine::translate((10.0, 0.0)));
+        ctx.transform(Affine::scale(2.0));
+        ctx.fill(Rect::new(0.0, 0.0, 2.0, 2.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_eq!(pixel(&dt, 9, 1), 0);
+    assert_eq!(pixel(&dt, 10, 1), RED);
+    assert_eq!(pixel(&dt, 13, 3), RED);
+    assert_eq!(pixel(&dt, 14, 1), 0);
+}
+
+#[test]
+fn transform_applies_to_strokes_and_clips() {
+    let dt = render(20, 20, |ctx| {
+        ctx.transform(Affine::translate((0.0, 10.0)));
+        ctx.stroke(
+            Line::new((0.0, 0.0), (20.0, 0.0)),
+            &Color::rgb8(255, 0, 0),
+            2.0,
+        );
+
+        ctx.clip(Rect::new(0.0, 5.0, 5.0, 10.0));
+        ctx.fill(Rect::new(0.0, -10.0, 20.0, 10.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_eq!(pixel(&dt, 15, 9), RED);
+    assert_eq!(pixel(&dt, 15, 10), RED);
+    assert_eq!(pixel(&dt, 15, 12), 0);
+    assert_eq!(pixel(&dt, 2, 17), RED);
+    assert_eq!(pixel(&dt, 2, 13), 0);
+    assert_eq!(pixel(&dt, 7, 17), 0);
+}
+
+#[test]
+fn draw_target_transform_applies_last() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(20, 20);
+    dt.set_transform(&Transform::scale(2.0, 2.0));
+
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+    ctx.transform(Affine::translate((3.0, 3.0)));
+    ctx.fill(Rect::new(0.0, 0.0, 1.0, 1.0), &Color::rgb8(255, 0, 0));
+    ctx.finish().unwrap();
+    drop(ctx);
+
+    assert_eq!(pixel(&dt, 5, 5), 0);
+    assert_eq!(pixel(&dt, 6, 6), RED);
+    assert_eq!(pixel(&dt, 7, 7), RED);
+    assert_eq!(pixel(&dt, 8, 8), 0);
+    assert_eq!(dt.get_transform(), &Transform::scale(2.0, 2.0));
+}
+
+#[test]
+fn restore_pops_nested_clips() {
+    let red = Color::rgb8(255, 0, 0);
+    let dt = render(20, 20, |ctx| {
+        ctx.save().unwrap();
+        ctx.clip(Rect::new(0.0, 0.0, 10.0, 10.0));
+        ctx.save().unwrap();
+        ctx.clip(Rect::new(5.0, 5.0, 20.0, 20.0));
+        ctx.fill(Rect::new(0.0, 0.0, 20.0, 20.0), &red);
+        ctx.restore().unwrap();
+
+        // Only the outer clip is left.
+        ctx.fill(Rect::new(0.0, 0.0, 20.0, 2.0), &red);
+        ctx.restore().unwrap();
+
+        // No clip is left.
+        ctx.fill(Rect::new(0.0, 18.0, 20.0, 20.0), &red);
+    });
+
+    assert_eq!(pixel(&dt, 7, 7), RED);
+    assert_eq!(pixel(&dt, 2, 2), 0);
+    assert_eq!(pixel(&dt, 12, 12), 0);
+    assert_eq!(pixel(&dt, 2, 1), RED);
+    assert_eq!(pixel(&dt, 12, 1), 0);
+    assert_eq!(pixel(&dt, 19, 19), RED);
+}
+
+#[test]
+fn clips_accumulate_within_a_state() {
+    let dt = render(20, 20, |ctx| {
+        ctx.save().unwrap();
+        ctx.clip(Rect::new(0.0, 0.0, 10.0, 20.0));
+        ctx.clip(Rect::new(0.0, 0.0, 20.0, 10.0));
+        ctx.fill(Rect::new(0.0, 0.0, 20.0, 20.0), &Color::rgb8(255, 0, 0));
+        ctx.restore().unwrap();
+    });
+
+    assert_eq!(pixel(&dt, 5, 5), RED);
+    assert_eq!(pixel(&dt, 15, 5), 0);
+    assert_eq!(pixel(&dt, 5, 15), 0);
+}
+
+#[test]
+fn finish_pops_remaining_clips() {
+    let mut dt = render(20, 20, |ctx| {
+        ctx.clip(Rect::new(0.0, 0.0, 5.0, 5.0));
+        ctx.save().unwrap();
+        ctx.clip(Rect::new(0.0, 0.0, 2.0, 2.0));
+        ctx.restore().unwrap();
+    });
+
+    // Drawing directly on the target is no longer clipped.
+    let source = Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0xff, 0, 0));
+    dt.fill_rect(0.0, 0.0, 20.0, 20.0, &source, &DrawOptions::new());
+    assert_eq!(pixel(&dt, 15, 15), RED);
+}
+
+#[test]
+fn clear_replaces_pixels() {
+    let dt = render(10, 10, |ctx| {
+        ctx.clear(None, Color::rgb8(255, 0, 0));
+        ctx.clear(Rect::new(0.0, 0.0, 10.0, 1.0), Color::TRANSPARENT);
+        ctx.clear(Rect::new(0.0, 1.0, 10.0, 2.0), Color::rgba8(0, 0, 255, 128));
+    });
+
+    assert_eq!(pixel(&dt, 5, 0), 0);
+    assert_eq!(pixel(&dt, 5, 1), 0x80000080);
+    assert_eq!(pixel(&dt, 5, 2), RED);
+}
+
+#[test]
+fn clear_ignores_transform_and_clip() {
+    let dt = render(10, 10, |ctx| {
+        ctx.clear(None, Color::rgb8(255, 0, 0));
+        ctx.clip(Rect::new(0.0, 0.0, 1.0, 1.0));
+        ctx.transform(Affine::translate((3.0, 3.0)));
+        ctx.clear(Rect::new(2.0, 2.0, 20.0, 4.0), Color::TRANSPARENT);
+    });
+
+    assert_eq!(pixel(&dt, 0, 0), RED);
+    assert_eq!(pixel(&dt, 9, 2), 0);
+    assert_eq!(pixel(&dt, 2, 3), 0);
+    assert_eq!(pixel(&dt, 2, 4), RED);
+    assert_eq!(pixel(&dt, 1, 3), RED);
+}
+
+#[test]
+fn status_reports_and_clears_errors() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(10, 10);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+
+    ctx.fill(Rect::new(0.0, f64::NAN, 1.0, 1.0), &Color::WHITE);
+    ctx.stroke(
+        Line::new((0.0, 0.0), (1.0, 1.0)),
+        &Color::WHITE,
+        f64::INFINITY,
+    );
+    assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+    assert!(ctx.status().is_ok());
+
+    ctx.clear(Rect::new(f64::NAN, 0.0, 1.0, 1.0), Color::WHITE);
+    assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+
+    assert!(ctx.make_image(2, 2, &[0; 3], ImageFormat::Rgb).is_err());
+    assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+
+    assert!(
+        ctx.capture_image_area(Rect::new(11.0, 5.0, 12.0, 6.0))
+            .is_err()
+    );
+    assert!(ctx.status().is_err());
+
+    let layout = ctx.text().new_text_layout("text").build().unwrap();
+    ctx.draw_text(&layout, (f64::NAN, 0.0));
+    assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+
+    ctx.finish().unwrap();
+}
+
+#[test]
+fn unbalanced_states_are_errors() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(10, 10);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+
+    assert!(matches!(ctx.restore(), Err(piet::Error::StackUnbalance)));
+    ctx.save().unwrap();
+    assert!(matches!(ctx.finish(), Err(piet::Error::StackUnbalance)));
+}
+
+#[test]
+fn layers_are_composited_as_a_group() {
+    let red = Color::rgb8(255, 0, 0);
+    let dt = render(20, 10, |ctx| {
+        // Without a layer, translucent shapes darken where they overlap.
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 5.0), &red.with_alpha(0.5));
+        ctx.fill(Rect::new(5.0, 0.0, 15.0, 5.0), &red.with_alpha(0.5));
+
+        ctx.push_layer(0.5, BlendMode::SrcOver, None::<Rect>);
+        ctx.fill(Rect::new(0.0, 5.0, 10.0, 10.0), &red);
+        ctx.fill(Rect::new(5.0, 5.0, 15.0, 10.0), &red);
+        ctx.pop_layer().unwrap();
+    });
+
+    assert_ne!(pixel(&dt, 7, 2), pixel(&dt, 2, 2));
+    assert_eq!(pixel(&dt, 2, 7), pixel(&dt, 2, 2));
+    assert_eq!(pixel(&dt, 7, 7), pixel(&dt, 2, 2));
+    assert_eq!(pixel(&dt, 12, 7), pixel(&dt, 2, 2));
+    assert_eq!(pixel(&dt, 17, 7), 0);
+}
+
+#[test]
+fn layer_clips_bound_the_layer() {
+    let dt = render(20, 20, |ctx| {
+        ctx.push_layer(
+            1.0,
+            BlendMode::SrcOver,
+            Some(Rect::new(0.0, 0.0, 10.0, 20.0)),
+        );
+        ctx.clip(Rect::new(0.0, 0.0, 20.0, 10.0));
+        ctx.fill(Rect::new(0.0, 0.0, 20.0, 20.0), &Color::rgb8(255, 0, 0));
+        ctx.pop_layer().unwrap();
+
+        // Popping the layer also pops the clips of its state.
+        ctx.fill(Rect::new(0.0, 15.0, 20.0, 20.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_eq!(pixel(&dt, 5, 5), RED);
+    assert_eq!(pixel(&dt, 15, 5), 0);
+    assert_eq!(pixel(&dt, 5, 12), 0);
+    assert_eq!(pixel(&dt, 15, 17), RED);
+}
+
+#[test]
+fn layers_are_popped_with_their_state() {
+    let dt = render(10, 10, |ctx| {
+        assert!(matches!(ctx.pop_layer(), Err(piet::Error::StackUnbalance)));
+
+        // Restoring the state that pushing the layer saved pops it, while states saved inside the
+        // layer have to be restored first.
+        ctx.push_layer(0.5, BlendMode::SrcOver, None::<Rect>);
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.save().unwrap();
+        assert!(matches!(ctx.pop_layer(), Err(piet::Error::StackUnbalance)));
+        ctx.restore().unwrap();
+        ctx.restore().unwrap();
+    });
+
+    assert_eq!(dt.get_data()[0], 0x80800000);
+}
+
+#[test]
+fn layers_with_invalid_opacity_are_transparent() {
+    let dt = render(10, 10, |ctx| {
+        ctx.push_layer(0.5, BlendMode::SrcOver, None::<Rect>);
+        ctx.push_layer(f32::NAN, BlendMode::SrcOver, None::<Rect>);
+        assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.pop_layer().unwrap();
+
+        // The outer layer is still open.
+        ctx.fill(Rect::new(0.0, 0.0, 5.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.pop_layer().unwrap();
+        assert!(matches!(ctx.pop_layer(), Err(piet::Error::StackUnbalance)));
+    });
+
+    assert_eq!(pixel(&dt, 2, 5), 0x80800000);
+    assert_eq!(pixel(&dt, 7, 5), 0);
+}
+
+#[test]
+fn layers_with_invalid_clips_are_transparent() {
+    let dt = render(10, 10, |ctx| {
+        ctx.push_layer(0.5, BlendMode::SrcOver, None::<Rect>);
+        ctx.push_layer(
+            1.0,
+            BlendMode::SrcOver,
+            Some(Rect::new(0.0, 0.0, f64::INFINITY, 10.0)),
+        );
+        assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.pop_layer().unwrap();
+
+        ctx.fill(Rect::new(0.0, 0.0, 5.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.pop_layer().unwrap();
+        assert!(matches!(ctx.pop_layer(), Err(piet::Error::StackUnbalance)));
+    });
+
+    assert_eq!(pixel(&dt, 2, 5), 0x80800000);
+    assert_eq!(pixel(&dt, 7, 5), 0);
+}
+
+#[test]
+fn clear_is_not_supported_in_layers() {
+    let dt = render(10, 10, |ctx| {
+        ctx.push_layer(1.0, BlendMode::SrcOver, None::<Rect>);
+        ctx.save().unwrap();
+        ctx.clear(None, Color::rgb8(255, 0, 0));
+        assert!(matches!(ctx.status(), Err(piet::Error::NotSupported)));
+        ctx.restore().unwrap();
+        ctx.pop_layer().unwrap();
+
+        ctx.clear(None, Color::rgb8(255, 0, 0));
+    });
+
+    assert!(dt.get_data().iter().all(|&p| p == RED));
+}
+
+#[test]
+fn capture_is_not_supported_in_layers() {
+    let mut cache = Cache::new();
+    let mut dt = DrawTarget::new(10, 10);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, &mut cache);
+    let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+
+    ctx.push_layer(1.0, BlendMode::SrcOver, None::<Rect>);
+    ctx.fill(rect, &Color::rgb8(255, 0, 0));
+    assert!(matches!(
+        ctx.capture_image_area(rect),
+        Err(piet::Error::NotSupported)
+    ));
+    assert!(matches!(ctx.status(), Err(piet::Error::NotSupported)));
+    ctx.pop_layer().unwrap();
+
+    assert!(ctx.capture_image_area(rect).is_ok());
+    ctx.finish().unwrap();
+}
+
+#[test]
+fn blend_modes_apply_to_later_drawing() {
+    let dt = render(20, 20, |ctx| {
+        ctx.clear(None, Color::rgb8(255, 128, 0));
+
+        ctx.save().unwrap();
+        ctx.set_blend_mode(BlendMode::Multiply);
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(128, 128, 255));
+        let image = ctx
+            .make_image(1, 1, &[128, 128, 255], ImageFormat::Rgb)
+            .unwrap();
+        ctx.draw_image(
+            &image,
+            Rect::new(10.0, 0.0, 20.0, 10.0),
+            InterpolationMode::NearestNeighbor,
+        );
+        ctx.restore().unwrap();
+
+        // The blend mode is restored with the state.
+        ctx.fill(
+            Rect::new(10.0, 10.0, 20.0, 20.0),
+            &Color::rgb8(128, 128, 255),
+        );
+
+        ctx.set_blend_mode(BlendMode::Screen);
+        ctx.stroke(
+            Line::new((0.0, 15.0), (10.0, 15.0)),
+            &Color::rgb8(0, 0, 255),
+            2.0,
+        );
+    });
+
+    assert_eq!(pixel(&dt, 5, 5), 0xff804000);
+    assert_eq!(pixel(&dt, 15, 5), 0xff804000);
+    assert_eq!(pixel(&dt, 15, 15), 0xff8080ff);
+    assert_eq!(pixel(&dt, 5, 15), 0xffff80ff);
+    assert_eq!(pixel(&dt, 5, 11), 0xffff8000);
+}
+
+#[test]
+fn blend_modes_apply_to_text() {
+    let dt = render(40, 40, |ctx| {
+        ctx.clear(None, Color::rgb8(255, 128, 0));
+        let layout = ctx
+            .text()
+            .new_text_layout("I")
+            .font(FontFamily::SANS_SERIF, 30.0)
+            .text_color(Color::rgb8(0, 0, 255))
+            .build()
+            .unwrap();
+        ctx.set_blend_mode(BlendMode::Screen);
+        ctx.draw_text(&layout, (5.0, 0.0));
+    });
+
+    // Screening blue over orange only ever raises the blue channel.
+    assert!(dt.get_data().contains(&0xffff80ff));
+    assert!(dt.get_data().iter().all(|&p| p & 0xffffff00 == 0xffff8000));
+}
+
+#[test]
+fn global_alpha_applies_to_every_operation() {
+    let dt = render(40, 40, |ctx| {
+        ctx.save().unwrap();
+        ctx.set_global_alpha(0.5);
+        ctx.fill(Rect::new(0.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.stroke(
+            Line::new((10.0, 5.0), (20.0, 5.0)),
+            &Color::rgb8(255, 0, 0),
+            2.0,
+        );
+        let image = ctx
+            .make_image(1, 1, &[0, 0, 255], ImageFormat::Rgb)
+            .unwrap();
+        ctx.draw_image(
+            &image,
+            Rect::new(20.0, 0.0, 30.0, 10.0),
+            InterpolationMode::NearestNeighbor,
+        );
+        ctx.blurred_rect(Rect::new(0.0, 20.0, 10.0, 30.0), 1.0, &Color::BLACK);
+        let layout = ctx
+            .text()
+            .new_text_layout("I")
+            .font(FontFamily::SANS_SERIF, 30.0)
+            .text_color(Color::BLACK)
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, (20.0, 10.0));
+        ctx.restore().unwrap();
+
+        // The global alpha is restored with the state.
+        ctx.fill(Rect::new(30.0, 30.0, 40.0, 40.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_eq!(pixel(&dt, 5, 5), 0x80800000);
+    assert_eq!(pixel(&dt, 15, 5), 0x80800000);
+    assert_eq!(pixel(&dt, 25, 5), 0x80000080);
+    assert_eq!(pixel(&dt, 5, 25), 0x80000000);
+    let text_alpha = (20..30).flat_map(|y| (20..30).map(move |x| (x, y)));
+    assert_eq!(
+        text_alpha.map(|(x, y)| pixel(&dt, x, y) >> 24).max(),
+        Some(0x80)
+    );
+    assert_eq!(pixel(&dt, 35, 35), RED);
+}
+
+#[test]
+fn global_alpha_is_clamped() {
+    let dt = render(10, 10, |ctx| {
+        ctx.set_global_alpha(2.0);
+        ctx.fill(Rect::new(0.0, 0.0, 5.0, 10.0), &Color::rgb8(255, 0, 0));
+        ctx.set_global_alpha(-1.0);
+        ctx.fill(Rect::new(5.0, 0.0, 10.0, 10.0), &Color::rgb8(255, 0, 0));
+        assert!(ctx.status().is_ok());
+
+        ctx.set_global_alpha(f32::NAN);
+        assert!(matches!(ctx.status(), Err(piet::Error::InvalidInput)));
+    });
+
+    assert_eq!(dt.get_data()[0], RED);
+    assert_eq!(dt.get_data()[5], 0);
+}
+
+/// Returns the number of pixels in `dt` that are neither transparent nor opaque.
+fn partially_covered(dt: &DrawTarget) -> usize {
+    (dt.get_data().iter())
+        .filter(|&&p| p >> 24 != 0 && p >> 24 != 0xff)
+        .count()
+}
+
+#[test]
+fn aliased_drawing_has_hard_edges() {
+    let dt = render(40, 40, |ctx| {
+        ctx.set_antialias(AntialiasMode::None);
+        ctx.transform(Affine::rotate(0.3));
+        ctx.fill(Circle::new((20.0, 10.0), 7.3), &Color::rgb8(255, 0, 0));
+        ctx.stroke(
+            Line::new((0.0, 0.0), (40.0, 33.0)),
+            &Color::rgb8(0, 255, 0),
+            1.7,
+        );
+        ctx.clip(Circle::new((20.0, 20.0), 9.6));
+        ctx.fill(Rect::new(0.0, 0.0, 40.0, 40.0), &Color::rgb8(0, 0, 255));
+    });
+
+    assert_eq!(partially_covered(&dt), 0);
+    assert!(dt.get_data().contains(&RED));
+    assert!(dt.get_data().contains(&0xff00ff00));
+    assert!(dt.get_data().contains(&0xff0000ff));
+
+    // Without the mode, the same drawing is antialiased.
+    let dt = render(40, 40, |ctx| {
+        ctx.transform(Affine::rotate(0.3));
+        ctx.fill(Circle::new((20.0, 10.0), 7.3), &Color::rgb8(255, 0, 0));
+    });
+    assert_ne!(partially_covered(&dt), 0);
+}
+
+#[test]
+fn aliased_clips_cover_the_pixels_of_aliased_fills() {
+    let shape = Circle::new((8.3, 10.2), 15.4);
+    let transform = Affine::rotate_about(0.4, (20.0, 20.0).into());
+    let filled = render(40, 40, |ctx| {
+        ctx.set_antialias(AntialiasMode::None);
+        ctx.transform(transform);
+        ctx.fill(shape, &Color::rgb8(255, 0, 0));
+    });
+    let clipped = render(40, 40, |ctx| {
+        ctx.set_antialias(AntialiasMode::None);
+        ctx.transform(transform);
+        ctx.clip(shape);
+        ctx.fill(
+            Rect::new(-100.0, -100.0, 100.0, 100.0),
+            &Color::rgb8(255, 0, 0),
+        );
+    });
+
+    // The circle extends past the top and left edges of the target.
+    assert_eq!(filled.get_data()[0], RED);
+    assert_eq!(clipped.get_data(), filled.get_data());
+}
+
+#[test]
+fn aliased_clips_outside_the_target_clip_everything() {
+    let dt = render(20, 20, |ctx| {
+        ctx.set_antialias(AntialiasMode::None);
+        ctx.clip(Rect::new(30.0, 30.0, 40.0, 40.0));
+        ctx.fill(Rect::new(0.0, 0.0, 40.0, 40.0), &Color::rgb8(255, 0, 0));
+    });
+
+    assert!(dt.get_data().iter().all(|&p| p == 0));
+}
+
+#[test]
+fn antialias_mode_is_restored_with_the_state() {
+    let dt = render(20, 20, |ctx| {
+        ctx.save().unwrap();
+        ctx.set_antialias(AntialiasMode::None);
+        ctx.restore().unwrap();
+        ctx.fill(Circle::new((10.0, 10.0), 5.5), &Color::rgb8(255, 0, 0));
+    });
+
+    assert_ne!(partially_covered(&dt), 0);
+}
@@ -0,0 +1,425 @@
+//! Tests for drawing text.
+
+use piet::{
+    Color, FontFamily, RenderContext, Text, TextAttribute, TextLayout, TextLayoutBuilder,
+    kurbo::{Affine, Point},
+};
+use piet_raqote::{Cache, RaqoteRenderContext};
+use raqote::DrawTarget;
+
+mod common;
+use common::render;
+
+/// Draws red `text` with `attribute` through `transform` onto a new target.
+fn draw_text(text: &str, attribute: Option<TextAttribute>, transform: Affine) -> DrawTarget {
+    render(200, 80, |ctx| {
+        let mut builder = ctx
+            .text()
+            .new_text_layout(text.to_owned())
+            .text_color(Color::rgb8(255, 0, 0));
+        if let Some(attribute) = attribute {
+            builder = builder.default_attribute(attribute);
+        }
+        let layout = builder.build().unwrap();
+        ctx.transform(transform);
+        ctx.draw_text(&layout, Point::new(5.0, 5.0));
+    })
+}
+
+/// Returns the longest horizontal run of inked pixels in each row.
+fn runs(dt: &DrawTarget) -> Vec<usize> {
+    dt.get_data()
+        .chunks_exact(dt.width() as usize)
+        .map(|row| {
+            row.split(|&p| p == 0)
+                .map(|run| run.len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Returns the rows that contain ink, and the longest run in any of them.
+fn inked_rows(dt: &DrawTarget) -> (std::ops::Range<usize>, usize) {
+    let runs = runs(dt);
+    let first = runs.iter().position(|&run| run > 0).unwrap();
+    let last = runs.iter().rposition(|&run| run > 0).unwrap();
+    (first..last + 1, runs.into_iter().max().unwrap())
+}
+
+#[test]
+fn underline_is_drawn_below_the_glyphs() {
+    let plain = draw_text("Hello world", None, Affine::IDENTITY);
+    let underline = draw_text(
+        "Hello world",
+        Some(TextAttribute::Underline(true)),
+        Affine::IDENTITY,
+    );
+
+    let (glyph_rows, longest_run) = inked_rows(&plain);
+    assert!(longest_run < 20);
+
+    let runs = runs(&underline);
+    let line = runs.iter().position(|&run| run > 60).unwrap();
+    assert!(line >= glyph_rows.end);
+
+    // The line has the color of the text.
+    let row = &underline.get_data()[line * 200..(line + 1) * 200];
+    assert!(row.iter().all(|&p| p & 0xffff == 0));
+}
+
+#[test]
+fn strikethrough_crosses_the_glyphs() {
+    let plain = draw_text("Hello world", None, Affine::IDENTITY);
+    let strikethrough = draw_text(
+        "Hello world",
+        Some(TextAttribute::Strikethrough(true)),
+        Affine::IDENTITY,
+    );
+
+    let (glyph_rows, _) = inked_rows(&plain);
+    let runs = runs(&strikethrough);
+    let line = runs.iter().position(|&run| run > 60).unwrap();
+    assert!(glyph_rows.start < line && line < glyph_rows.end);
+}
+
+#[test]
+fn decorations_follow_the_transform() {
+    let underline = draw_text(
+        "Hello world",
+        Some(TextAttribute::Underline(true)),
+        Affine::IDENTITY,
+    );
+    let scaled = draw_text(
+        "Hello world",
+        Some(TextAttribute::Underline(true)),
+        Affine::scale(2.0),
+    );
+
+    let (_, width) = inked_rows(&underline);
+    let (_, scaled_width) = inked_rows(&scaled);
+    assert!(scaled_width.abs_diff(2 * width) <= 2);
+
+    let line = runs(&underline).iter().position(|&run| run > 60).unwrap();
+    let scaled_line = runs(&scaled).iter().position(|&run| run > 120).unwrap();
+    assert!(scaled_line.abs_diff(2 * line) <= 2);
+}
+
+/// Draws black `text` at `font_size` through `transform` onto a new 400 by 200 target.
+fn draw_sized(text: &str, font_size: f64, transform: Affine) -> DrawTarget {
+    render(400, 200, |ctx| {
+        let layout = ctx
+            .text()
+            .new_text_layout(text.to_owned())
+            .font(FontFamily::SANS_SERIF, font_size)
+            .build()
+            .unwrap();
+        ctx.transform(transform);
+        ctx.draw_text(&layout, Point::new(5.0, 5.0));
+    })
+}
+
+/// Returns the bounds of the pixels that are not transparent, as `(x0, y0, x1, y1)`.
+fn ink_bounds(dt: &DrawTarget) -> (usize, usize, usize, usize) {
+    let width = dt.width() as usize;
+    let inked: Vec<_> = (dt.get_data().iter().enumerate())
+        .filter(|(_, p)| **p != 0)
+        .map(|(i, _)| (i % width, i / width))
+        .collect();
+    let xs = inked.iter().map(|&(x, _)| x);
+    let ys = inked.iter().map(|&(_, y)| y);
+    (
+        xs.clone().min().unwrap(),
+        ys.clone().min().unwrap(),
+        xs.max().unwrap() + 1,
+        ys.max().unwrap() + 1,
+    )
+}
+
+#[test]
+fn text_is_scaled_by_the_transform() {
+    let (x0, y0, x1, y1) = ink_bounds(&draw_sized("Hello", 12.0, Affine::IDENTITY));
+    let (sx0, sy0, sx1, sy1) = ink_bounds(&draw_sized("Hello", 12.0, Affine::scale(3.0)));
+
+    for (scaled, plain) in [(sx0, x0), (sy0, y0), (sx1, x1), (sy1, y1)] {
+        assert!(scaled.abs_diff(3 * plain) <= 3, "{scaled} {plain}");
+    }
+}
+
+#[test]
+fn text_is_rotated_by_the_transform() {
+    let (x0, y0, x1, y1) = ink_bounds(&draw_sized("Hello", 12.0, Affine::IDENTITY));
+    let rotated = draw_sized(
+        "Hello",
+        12.0,
+        Affine::translate((200.0, 0.0)) * Affine::rotate(std::f64::consts::FRAC_PI_2),
+    );
+
+    // A quarter turn maps (x, y) to (200 - y, x).
+    let (rx0, ry0, rx1, ry1) = ink_bounds(&rotated);
+    for (rotated, plain) in [(rx0, 200 - y1), (ry0, x0), (rx1, 200 - y0), (ry1, x1)] {
+        assert!(rotated.abs_diff(plain) <= 1, "{rotated} {plain}");
+    }
+}
+
+#[test]
+fn scaled_text_is_laid_out_at_the_device_size() {
+    // Text under a uniform scale is laid out as if the font were that much larger, so glyph
+    // positions are not rounded to the subpixel steps of the unscaled font.
+    let scaled = draw_sized("Hello world", 12.0, Affine::scale(4.0));
+    let large = draw_sized("Hello world", 48.0, Affine::translate((15.0, 15.0)));
+
+    let difference = (scaled.get_data().iter().zip(large.get_data()))
+        .map(|(a, b)| (a >> 24).abs_diff(b >> 24))
+        .max()
+        .unwrap();
+    assert!(difference <= 2, "{difference}");
+}
+
+/// Draws `text` at the same position as every other call, using `cache`.
+fn draw_cached(cache: &mut Cache, text: &str) {
+    let mut dt = DrawTarget::new(100, 40);
+    let mut ctx = RaqoteRenderContext::new(&mut dt, cache);
+    let layout = ctx.text().new_text_layout(text.to_owned()).build().unwrap();
+    ctx.draw_text(&layout, Point::new(5.0, 5.0));
+    ctx.finish().unwrap();
+}
+
+#[test]
+fn glyph_paths_are_cached() {
+    let mut cache = Cache::new();
+    draw_cached(&mut cache, "ab");
+    draw_cached(&mut cache, "ab");
+
+    let stats = cache.glyph_path_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 2));
+}
+
+#[test]
+fn glyph_path_limit_evicts_least_recently_used() {
+    let mut cache = Cache::new();
+    cache.set_glyph_path_limit(2);
+    for text in ["a", "b", "a", "c", "a", "b"] {
+        draw_cached(&mut cache, text);
+    }
+
+    // "c" evicted "b", which was used less recently than "a".
+    let stats = cache.glyph_path_stats();
+    assert_eq!((stats.hits, stats.misses), (2, 4));
+}
+
+#[test]
+fn glyph_path_limit_keeps_one_path() {
+    let mut cache = Cache::new();
+    draw_cached(&mut cache, "a");
+    cache.set_glyph_path_limit(0);
+    draw_cached(&mut cache, "a");
+
+    let stats = cache.glyph_path_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+}
+
+#[test]
+fn glyph_paths_cycling_past_the_limit_miss() {
+    let mut cache = Cache::new();
+    cache.set_glyph_path_limit(3);
+    for _ in 0..50 {
+        draw_cached(&mut cache, "abcd");
+    }
+
+    // Every glyph evicts the one used least recently, so none of them is ever found.
+    let stats = cache.glyph_path_stats();
+    assert_eq!((stats.hits, stats.misses), (0, 200));
+}
+
+/// Builds a font without outlines, whose glyphs only exist as 16 ppem bitmaps.
+///
+/// "A" is a 4 by 4 blue color bitmap and "B" a 4 by 4 alpha mask, both 2 pixels right of the
+/// origin and with their top 12 pixels above the baseline.
+fn bitmap_font() -> Vec<u8> {
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    // Each glyph is stored as image format 1: small metrics (height, width, bearing x, bearing y,
+    // advance) followed by the pixels.
+    let glyphs: [(u8, Vec<u8>); 2] = [(32, [0, 0, 255, 255].repeat(16)), (8, [255; 16].to_vec())];
+    let mut cbdt = u32s(&[0x0003_0000]);
+    let mut cblc = u32s(&[0x0003_0000, glyphs.len() as u32]);
+    let mut index_tables = Vec::new();
+    let index_offset = 8 + 48 * glyphs.len();
+    for (i, (bit_depth, pixels)) in glyphs.iter().enumerate() {
+        let glyph = i as u16 + 1;
+        let image_offset = cbdt.len() as u32;
+        cbdt.extend([4, 4, 2, 12, 18]);
+        cbdt.extend(pixels);
+        let image_size = cbdt.len() as u32 - image_offset;
+
+        // A strike with a single index subtable of format 1, which holds offsets into CBDT.
+        let array_offset = (index_offset + index_tables.len()) as u32;
+        cblc.extend(u32s(&[array_offset, 24, 1, 0]));
+        cblc.extend([0; 24]);
+        cblc.extend(u16s(&[glyph, glyph]));
+        cblc.extend([16, 16, *bit_depth, 1]);
+        index_tables.extend(u16s(&[glyph, glyph]));
+        index_tables.extend(u32s(&[8]));
+        index_tables.extend(u16s(&[1, 1]));
+        index_tables.extend(u32s(&[image_offset, 0, image_size]));
+    }
+    cblc.extend(index_tables);
+
+    let name = "Bitmap Test";
+    let name: Vec<u8> = name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut name_table = u16s(&[0, 2, 30]);
+    for id in [1, 6] {
+        name_table.extend(u16s(&[3, 1, 0x409, id, name.len() as u16, 0]));
+    }
+    name_table.extend(&name);
+
+    // Maps "A" and "B" to glyphs 1 and 2, with a format 4 subtable of two segments.
+    let (start_codes, end_codes) = ([0x41, 0xffff], [0x42, 0xffff]);
+    let deltas = [0u16.wrapping_sub(0x40), 1];
+    let cmap = [
+        u16s(&[0, 1, 3, 1]),
+        u32s(&[12]),
+        u16s(&[4, 32, 0, 4, 4, 1, 0]),
+        u16s(&end_codes),
+        u16s(&[0]),
+        u16s(&start_codes),
+        u16s(&deltas),
+        u16s(&[0, 0]),
+    ]
+    .concat();
+
+    let head = [
+        u32s(&[0x0001_0000, 0x0001_0000, 0, 0x5f0f_3cf5]),
+        u16s(&[0, 16]),
+        vec![0; 16],
+        u16s(&[0, 0, 16, 16, 0, 8, 2, 0, 0]),
+    ]
+    .concat();
+    let hhea = [
+        u32s(&[0x0001_0000]),
+        u16s(&[14, 0u16.wrapping_sub(4), 0, 18, 0, 0, 16, 1, 0, 0]),
+        vec![0; 10],
+        u16s(&[3]),
+    ]
+    .concat();
+    let tables: [(&[u8; 4], Vec<u8>); 8] = [
+        (b"CBDT", cbdt),
+        (b"CBLC", cblc),
+        (b"cmap", cmap),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", u16s(&[0, 0, 18, 0, 18, 0])),
+        (b"maxp", [u32s(&[0x0000_5000]), u16s(&[3])].concat()),
+        (b"name", name_table),
+    ];
+
+    let mut font = u32s(&[0x0001_0000]);
+    font.extend(u16s(&[tables.len() as u16, 128, 3, 0]));
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        font.extend(*tag);
+        font.extend(u32s(&[0, offset as u32, data.len() as u32]));
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables {
+        let padding = data.len().next_multiple_of(4) - data.len();
+        font.extend(data);
+        font.extend(vec![0; padding]);
+    }
+    font
+}
+
+/// Draws `text` in the bitmap font at `pos` through `transform`, in red.
+///
+/// The font size of 12 points is 16 pixels, the size of the bitmaps.
+/// Returns the target, and the baseline of the first line relative to the layout.
+fn draw_bitmap_text(text: &str, pos: Point, transform: Affine) -> (DrawTarget, f64) {
+    let mut baseline = 0.0;
+    let dt = render(100, 100, |ctx| {
+        let family = ctx.text().load_font(&bitmap_font()).unwrap();
+        let layout = ctx
+            .text()
+            .new_text_layout(text.to_owned())
+            .font(family, 12.0)
+            .text_color(Color::rgb8(255, 0, 0))
+            .build()
+            .unwrap();
+        let line = layout.line_metric(0).unwrap();
+        baseline = line.y_offset + line.baseline;
+        ctx.transform(transform);
+        ctx.draw_text(&layout, pos);
+    });
+    (dt, baseline)
+}
+
+#[test]
+fn bitmap_glyphs_are_placed_at_the_glyph_origin() {
+    let (dt, baseline) = draw_bitmap_text("A", Point::new(20.0, 30.0), Affine::IDENTITY);
+
+    // The bitmap's own bearing places it relative to the pen position on the baseline.
+    let top = 30 + baseline as usize - 12;
+    assert_eq!(ink_bounds(&dt), (22, top, 26, top + 4));
+
+    // Color bitmaps keep their own color, and are drawn without filtering at whole pixels.
+    let data = dt.get_data();
+    assert!((top..top + 4).all(|y| data[y * 100 + 22..y * 100 + 26] == [0xff0000ff; 4]));
+}
+
+#[test]
+fn bitmap_glyphs_follow_the_transform() {
+    let (moved, _) = draw_bitmap_text("A", Point::new(20.0, 30.0), Affine::translate((7.0, 9.0)));
+    let (dt, baseline) = draw_bitmap_text("A", Point::new(27.0, 39.0), Affine::IDENTITY);
+    assert_eq!(moved.get_data(), dt.get_data());
+
+    // Under a uniform scale the bitmap is scaled to the device size before it is drawn.
+    let (scaled, _) = draw_bitmap_text("A", Point::new(10.0, 15.0), Affine::scale(2.0));
+    let top = 2 * (15 + baseline as usize - 12);
+    assert_eq!(ink_bounds(&scaled), (24, top, 32, top + 8));
+}
+
+#[test]
+fn bitmap_glyphs_are_snapped_to_whole_pixels() {
+    // Bitmaps are drawn at the whole pixel that their position falls in, rather than resampled
+    // at a fractional one.
+    let (dt, _) = draw_bitmap_text("A", Point::new(20.0, 30.0), Affine::IDENTITY);
+    for pos in [Point::new(20.4, 30.0), Point::new(20.0, 30.3)] {
+        let (moved, _) = draw_bitmap_text("A", pos, Affine::IDENTITY);
+        assert_eq!(moved.get_data(), dt.get_data(), "{pos:?}");
+    }
+
+    // The translation of the transform is part of the position.
+    let (moved, _) = draw_bitmap_text("A", Point::new(20.0, 30.0), Affine::translate((0.4, 0.3)));
+    assert_eq!(moved.get_data(), dt.get_data());
+}
+
+#[test]
+fn scaled_bitmap_glyphs_are_snapped_to_whole_device_pixels() {
+    // Under a scale, positions are snapped on the device rather than in user space.
+    let transform = Affine::scale(2.0);
+    let (dt, _) = draw_bitmap_text("A", Point::new(10.0, 15.0), transform);
+    let (moved, _) = draw_bitmap_text("A", Point::new(10.2, 15.0), transform);
+    assert_eq!(moved.get_data(), dt.get_data());
+
+    // Half a user-space pixel is a whole device pixel.
+    let (moved, _) = draw_bitmap_text("A", Point::new(10.5, 15.0), transform);
+    let (data, moved) = (dt.get_data(), moved.get_data());
+    assert_eq!(data[..data.len() - 1], moved[1..]);
+}
+
+#[test]
+fn bitmap_masks_are_tinted_with_the_text_color() {
+    let (dt, baseline) = draw_bitmap_text("AB", Point::new(20.0, 30.0), Affine::IDENTITY);
+
+    // "B" follows "A" by its advance, and its mask is filled with the text color.
+    let top = 30 + baseline as usize - 12;
+    assert_eq!(ink_bounds(&dt), (22, top, 44, top + 4));
+    let data = dt.get_data();
+    assert_eq!(data[top * 100 + 22..top * 100 + 26], [0xff0000ff; 4]);
+    assert_eq!(data[top * 100 + 40..top * 100 + 44], [0xffff0000; 4]);
+}